@@ -0,0 +1,60 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// /proc/stat の内容から "btime" の行の値 (起動時刻の UNIX 時間) を取り出す
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_btime(stat: &str) -> Option<u64> {
+    stat.lines()
+        .find_map(|l| l.strip_prefix("btime "))
+        .and_then(|v| v.trim().parse().ok())
+}
+
+/// システムが起動した時刻を返す
+#[cfg(target_os = "linux")]
+pub fn boot_time() -> Result<SystemTime, String> {
+    let stat = std::fs::read_to_string("/proc/stat").map_err(|e| format!("/proc/stat: {}", e))?;
+    let secs = parse_btime(&stat).ok_or("/proc/stat: btime not found")?;
+    Ok(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// システムが起動した時刻を返す
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+pub fn boot_time() -> Result<SystemTime, String> {
+    let mut mib = [libc::CTL_KERN, libc::KERN_BOOTTIME];
+    let mut tv = libc::timeval {
+        tv_sec: 0,
+        tv_usec: 0,
+    };
+    let mut len = std::mem::size_of::<libc::timeval>();
+    let res = unsafe {
+        libc::sysctl(
+            mib.as_mut_ptr(),
+            mib.len() as libc::c_uint,
+            &mut tv as *mut libc::timeval as *mut libc::c_void,
+            &mut len,
+            std::ptr::null_mut(),
+            0,
+        )
+    };
+    if res != 0 {
+        return Err("sysctl kern.boottime failed".to_string());
+    }
+
+    Ok(UNIX_EPOCH + Duration::new(tv.tv_sec as u64, tv.tv_usec as u32 * 1000))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "freebsd")))]
+pub fn boot_time() -> Result<SystemTime, String> {
+    Err("--since-boot is not supported on this platform".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_btime;
+
+    #[test]
+    fn test_parse_btime() {
+        let stat = "cpu  1 2 3 4\nintr 5\nbtime 1700000000\nprocesses 42\n";
+        assert_eq!(parse_btime(stat), Some(1700000000));
+        assert_eq!(parse_btime("cpu  1 2 3 4\n"), None);
+    }
+}
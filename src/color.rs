@@ -0,0 +1,382 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::{self, IsTerminal, Read},
+    os::unix::fs::{FileTypeExt, PermissionsExt},
+    path::Path,
+};
+
+use clap::ValueEnum;
+
+/// GNU dircolors の既定値のうち、lsr が扱う種別のものと、lsr 独自の sm (集計の行)
+const DEFAULT_COLORS: &str = "di=01;34:ln=01;36:or=40;31;01:pi=40;33:so=01;35:bd=40;33;01:\
+    cd=40;33;01:su=37;41:sg=30;43:tw=30;42:ow=34;42:st=37;44:ex=01;32:sm=01";
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorWhen {
+    Always,
+    Auto,
+    Never,
+}
+
+impl ColorWhen {
+    pub fn enabled(&self) -> bool {
+        match self {
+            ColorWhen::Always => true,
+            ColorWhen::Auto => io::stdout().is_terminal(),
+            ColorWhen::Never => false,
+        }
+    }
+}
+
+/// LS_COLORS 形式の指定を種別 (di, ln など) と拡張子ごとに保持する。
+/// SGR コードは "01;03;32" のような属性の並びを分けずにそのまま持つ
+#[derive(Debug)]
+pub struct ColorMap {
+    types: HashMap<String, String>,
+    extensions: HashMap<String, String>,
+    /// 実行ビットのない "#!" で始まるファイルも実行可能ファイルの色にする
+    pub detect_scripts: bool,
+}
+
+impl ColorMap {
+    pub fn from_env() -> Self {
+        Self::parse(&env::var("LS_COLORS").unwrap_or_default())
+    }
+
+    /// 既定値に "di=01;34:*.txt=32" のような指定を上書きしたものを返す
+    pub fn parse(spec: &str) -> Self {
+        let mut map = ColorMap {
+            types: HashMap::new(),
+            extensions: HashMap::new(),
+            detect_scripts: false,
+        };
+
+        for entry in DEFAULT_COLORS.split(':').chain(spec.split(':')) {
+            if let Some((key, code)) = entry.split_once('=') {
+                match key.strip_prefix("*.") {
+                    Some(ext) => map.extensions.insert(ext.to_string(), code.to_string()),
+                    None => map.types.insert(key.to_string(), code.to_string()),
+                };
+            }
+        }
+
+        map
+    }
+
+    /// 既定値にテーマファイルの指定を上書きしたものを返す。テーマは次の形式の TOML で、
+    /// [types] には di や ln などの種別、[extensions] にはドットを除いた拡張子ごとに
+    /// SGR コードを文字列で書く。# から行末まではコメントとして読み飛ばす
+    ///
+    /// ```toml
+    /// [types]
+    /// di = "01;35"
+    ///
+    /// [extensions]
+    /// txt = "32"
+    /// ```
+    pub fn from_theme(theme: &str) -> Result<Self, String> {
+        let mut map = Self::parse("");
+        let mut section = None;
+
+        for (i, line) in theme.lines().enumerate() {
+            let error = |reason: &str| format!("line {}: {}", i + 1, reason);
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = match name.trim() {
+                    "types" => Some(true),
+                    "extensions" => Some(false),
+                    name => return Err(error(&format!("unknown section [{}]", name))),
+                };
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| error("expected key = \"code\""))?;
+            let (key, value) = (key.trim().trim_matches('"'), value.trim());
+            let code = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .ok_or_else(|| error("the color code must be a quoted string"))?;
+            if !code.chars().all(|c| c.is_ascii_digit() || c == ';') {
+                return Err(error(&format!("invalid color code \"{}\"", code)));
+            }
+            let colors = match section {
+                Some(true) => &mut map.types,
+                Some(false) => &mut map.extensions,
+                None => return Err(error("key outside of [types] or [extensions]")),
+            };
+            colors.insert(key.to_string(), code.to_string());
+        }
+
+        Ok(map)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.types
+            .get(key)
+            .map(|c| c.as_str())
+            .filter(|c| !c.is_empty())
+    }
+
+    fn get_extension(&self, path: &Path) -> Option<&str> {
+        let ext = path.extension()?.to_str()?;
+        self.extensions.get(ext).map(|c| c.as_str())
+    }
+}
+
+/// ファイルが "#!" で始まれば true を返す
+fn has_shebang(path: &Path) -> bool {
+    let mut head = [0; 2];
+    fs::File::open(path)
+        .and_then(|mut f| f.read_exact(&mut head))
+        .is_ok_and(|_| &head == b"#!")
+}
+
+/// setuid、setgid、スティッキービットを持つファイルの SGR コードを返す
+fn special_bits_code(mode: u32, map: &ColorMap) -> Option<&str> {
+    if mode & 0o4000 != 0 {
+        map.get("su")
+    } else if mode & 0o2000 != 0 {
+        map.get("sg")
+    } else if mode & 0o1000 != 0 {
+        map.get("st")
+    } else {
+        None
+    }
+}
+
+/// ファイルに security.capability 拡張属性 (setcap(8) で付けるケーパビリティ) があれば true を返す
+#[cfg(target_os = "linux")]
+fn has_capability(path: &Path) -> bool {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    let name = c"security.capability";
+    let size = unsafe { libc::getxattr(path.as_ptr(), name.as_ptr(), std::ptr::null_mut(), 0) };
+    size > 0
+}
+
+#[cfg(not(target_os = "linux"))]
+fn has_capability(_path: &Path) -> bool {
+    false
+}
+
+/// パスの種別に応じた SGR コードを返す
+fn color_code<'a>(path: &Path, map: &'a ColorMap) -> Option<&'a str> {
+    // 存在しないパスは mi で表す
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return map.get("mi");
+    };
+    let file_type = metadata.file_type();
+
+    if file_type.is_symlink() {
+        // リンク先を stat できなければリンク切れ (orphan) とみなす
+        return match fs::metadata(path) {
+            Ok(_) => map.get("ln"),
+            Err(_) => map
+                .get("or")
+                .or_else(|| map.get("mi"))
+                .or_else(|| map.get("ln")),
+        };
+    }
+
+    let mode = metadata.permissions().mode();
+
+    if file_type.is_dir() {
+        // 他者が書き込み可能なディレクトリとスティッキービットを持つディレクトリは区別する
+        let other_writable = mode & 0o002 != 0;
+        let sticky = mode & 0o1000 != 0;
+        match (other_writable, sticky) {
+            (true, true) => map.get("tw"),
+            (true, false) => map.get("ow"),
+            (false, true) => map.get("st"),
+            (false, false) => None,
+        }
+        .or_else(|| map.get("di"))
+    } else if file_type.is_fifo() {
+        map.get("pi")
+    } else if file_type.is_socket() {
+        map.get("so")
+    } else if file_type.is_block_device() {
+        map.get("bd")
+    } else if file_type.is_char_device() {
+        map.get("cd")
+    } else if let Some(code) = special_bits_code(mode, map) {
+        Some(code)
+    } else if map.get("ca").is_some() && has_capability(path) {
+        map.get("ca")
+    } else if mode & 0o111 != 0 || (map.detect_scripts && has_shebang(path)) {
+        map.get("ex")
+    } else {
+        map.get_extension(path).or_else(|| map.get("fi"))
+    }
+}
+
+/// --size-scale-breaks の区間ごとの色。区間が多い場合は最後の色を使い続ける
+const SIZE_SCALE_COLORS: [&str; 5] = ["32", "33", "31", "35", "01;35"];
+
+/// size が breaks で区切られたどの区間にあるかに応じた SGR コードを返す
+pub fn size_scale_code(size: u64, breaks: &[u64]) -> &'static str {
+    let bucket = breaks.iter().filter(|&&b| size >= b).count();
+    SIZE_SCALE_COLORS[bucket.min(SIZE_SCALE_COLORS.len() - 1)]
+}
+
+/// 端末が 24 ビットカラーに対応していれば true を返す
+pub fn truecolor() -> bool {
+    env::var("COLORTERM").is_ok_and(|v| v == "truecolor" || v == "24bit")
+}
+
+/// 0.0 から 1.0 の fraction を緑から赤への色にした SGR コードを返す。
+/// truecolor でなければ緑・黄・赤の 3 段階にする
+pub fn gradient_code(fraction: f64, truecolor: bool) -> String {
+    let fraction = fraction.clamp(0.0, 1.0);
+    if truecolor {
+        let red = (255.0 * fraction).round() as u8;
+        let green = (255.0 * (1.0 - fraction)).round() as u8;
+        format!("38;2;{};{};0", red, green)
+    } else {
+        let index = ((fraction * 3.0) as usize).min(2);
+        ["32", "33", "31"][index].to_string()
+    }
+}
+
+/// text を SGR コード code で囲んで返す
+pub fn paint(code: Option<&str>, text: &str) -> String {
+    match code {
+        Some(code) => format!("\x1b[{}m{}\x1b[0m", code, text),
+        None => text.to_string(),
+    }
+}
+
+/// text の最後の SGR シーケンスがリセットでなければ、末尾の改行の前にリセットを足す。
+/// 一覧を途中で打ち切っても、端末に色が残らないようにする
+pub fn ensure_reset(text: &mut String) {
+    const RESET: &str = "\x1b[0m";
+    let Some(start) = text.rfind("\x1b[") else {
+        return;
+    };
+    if text[start..].starts_with(RESET) {
+        return;
+    }
+    let end = text.trim_end_matches('\n').len();
+    text.insert_str(end, RESET);
+}
+
+/// 集計の行 text を sm の色で囲んで返す。map がない場合は色を付けない
+pub fn summary(text: &str, map: Option<&ColorMap>) -> String {
+    paint(map.and_then(|m| m.get("sm")), text)
+}
+
+/// name を path の種別に応じた色で囲んで返す
+pub fn colorize(path: &Path, name: &str, map: &ColorMap) -> String {
+    paint(color_code(path, map), name)
+}
+
+/// 種別ごとの色の見本を 1 行ずつ並べて返す。map がない場合は色を付けない
+pub fn legend(map: Option<&ColorMap>) -> String {
+    const LABELS: [(&str, &str); 13] = [
+        ("di", "directory"),
+        ("ln", "symlink"),
+        ("or", "orphan"),
+        ("ex", "executable"),
+        ("pi", "fifo"),
+        ("so", "socket"),
+        ("bd", "block device"),
+        ("cd", "character device"),
+        ("su", "setuid"),
+        ("sg", "setgid"),
+        ("ow", "other-writable"),
+        ("tw", "sticky other-writable"),
+        ("st", "sticky"),
+    ];
+
+    LABELS
+        .iter()
+        .map(|(key, label)| paint(map.and_then(|m| m.get(key)), label) + "\n")
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ensure_reset, gradient_code, size_scale_code, ColorMap};
+
+    #[test]
+    fn test_parse() {
+        // 既定値
+        let map = ColorMap::parse("");
+        assert_eq!(map.get("di"), Some("01;34"));
+        assert_eq!(map.get("or"), Some("40;31;01"));
+
+        // LS_COLORS の指定で既定値を上書きする
+        let map = ColorMap::parse("di=01;35:or=:*.txt=32");
+        assert_eq!(map.get("di"), Some("01;35"));
+        assert_eq!(map.get("or"), None);
+        assert_eq!(map.extensions.get("txt").map(|c| c.as_str()), Some("32"));
+    }
+
+    #[test]
+    fn test_from_theme() {
+        let map = ColorMap::from_theme(
+            "# theme\n[types]\ndi = \"01;35\"\n\n[extensions]\ntxt = \"32\" # text\n",
+        )
+        .unwrap();
+        assert_eq!(map.get("di"), Some("01;35"));
+        assert_eq!(map.get("ln"), Some("01;36"));
+        assert_eq!(map.extensions.get("txt").map(|c| c.as_str()), Some("32"));
+
+        assert_eq!(
+            ColorMap::from_theme("di = \"1\"").unwrap_err(),
+            "line 1: key outside of [types] or [extensions]"
+        );
+        assert_eq!(
+            ColorMap::from_theme("[types]\ndi = 1").unwrap_err(),
+            "line 2: the color code must be a quoted string"
+        );
+        assert_eq!(
+            ColorMap::from_theme("[colors]").unwrap_err(),
+            "line 1: unknown section [colors]"
+        );
+    }
+
+    #[test]
+    fn test_ensure_reset() {
+        let mut text = "\x1b[32mfox.txt\x1b[0m\n".to_string();
+        ensure_reset(&mut text);
+        assert_eq!(text, "\x1b[32mfox.txt\x1b[0m\n");
+
+        let mut text = "\x1b[0m\x1b[32mfox.txt\n".to_string();
+        ensure_reset(&mut text);
+        assert_eq!(text, "\x1b[0m\x1b[32mfox.txt\x1b[0m\n");
+
+        let mut text = "fox.txt\n".to_string();
+        ensure_reset(&mut text);
+        assert_eq!(text, "fox.txt\n");
+    }
+
+    #[test]
+    fn test_size_scale_code() {
+        let breaks = [1024, 1024 * 1024];
+        assert_eq!(size_scale_code(0, &breaks), "32");
+        assert_eq!(size_scale_code(1024, &breaks), "33");
+        assert_eq!(size_scale_code(5 * 1024 * 1024, &breaks), "31");
+        assert_eq!(size_scale_code(u64::MAX, &[1, 2, 3, 4, 5, 6]), "01;35");
+    }
+
+    #[test]
+    fn test_gradient_code() {
+        assert_eq!(gradient_code(0.0, true), "38;2;0;255;0");
+        assert_eq!(gradient_code(0.5, true), "38;2;128;128;0");
+        assert_eq!(gradient_code(2.0, true), "38;2;255;0;0");
+
+        assert_eq!(gradient_code(0.0, false), "32");
+        assert_eq!(gradient_code(0.5, false), "33");
+        assert_eq!(gradient_code(1.0, false), "31");
+    }
+}
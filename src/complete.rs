@@ -0,0 +1,22 @@
+use clap::{Command, ValueEnum};
+use clap_complete::{generate, shells};
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// shell 向けの補完スクリプトを cmd の定義から clap_complete で作って返す
+pub fn completions(cmd: &mut Command, shell: Shell) -> String {
+    let name = cmd.get_name().to_string();
+    let mut script = vec![];
+    match shell {
+        Shell::Bash => generate(shells::Bash, cmd, name, &mut script),
+        Shell::Zsh => generate(shells::Zsh, cmd, name, &mut script),
+        Shell::Fish => generate(shells::Fish, cmd, name, &mut script),
+    }
+
+    String::from_utf8_lossy(&script).to_string()
+}
@@ -0,0 +1,145 @@
+use std::path::Path;
+
+/// BSD の st_flags の各ビットと chflags(1) での名前
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+const FLAG_NAMES: &[(u32, &str)] = &[
+    (0x0000_0001, "nodump"),
+    (0x0000_0002, "uchg"),
+    (0x0000_0004, "uappnd"),
+    (0x0000_0008, "opaque"),
+    (0x0000_8000, "hidden"),
+    (0x0001_0000, "arch"),
+    (0x0002_0000, "schg"),
+    (0x0004_0000, "sappnd"),
+];
+
+/// Linux の FS_IOC_GETFLAGS で得られる属性の各ビットと名前
+#[cfg(target_os = "linux")]
+const FLAG_NAMES: &[(u32, &str)] = &[
+    (0x0000_0010, "immutable"),
+    (0x0000_0020, "append"),
+    (0x0000_0040, "nodump"),
+    (0x0000_0080, "noatime"),
+];
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "freebsd")))]
+const FLAG_NAMES: &[(u32, &str)] = &[];
+
+/// --attrs でモードの後に付ける、Linux の属性の印 (lsattr(1) と同じ文字)
+#[cfg(target_os = "linux")]
+const ATTR_INDICATORS: &[(u32, char)] = &[(0x0000_0010, 'i'), (0x0000_0020, 'a')];
+
+#[cfg(not(target_os = "linux"))]
+const ATTR_INDICATORS: &[(u32, char)] = &[];
+
+/// path のファイルフラグを返す。取得できない場合は None
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+pub fn file_flags(path: &Path) -> Option<u32> {
+    #[cfg(target_os = "freebsd")]
+    use std::os::freebsd::fs::MetadataExt;
+    #[cfg(target_os = "macos")]
+    use std::os::macos::fs::MetadataExt;
+
+    path.symlink_metadata().ok().map(|m| m.st_flags())
+}
+
+/// path のファイルフラグを返す。ファイルシステムが対応していない場合と、
+/// 通常のファイルとディレクトリ以外の場合は None
+#[cfg(target_os = "linux")]
+pub fn file_flags(path: &Path) -> Option<u32> {
+    use std::{
+        fs::OpenOptions,
+        os::unix::{fs::OpenOptionsExt, io::AsRawFd},
+    };
+
+    // デバイスは開くだけで巻き戻しなどが起きるので、e2fsprogs の fgetflags と同じく
+    // 通常のファイルとディレクトリだけを開く。シンボリックリンクは辿らない
+    let file_type = path.symlink_metadata().ok()?.file_type();
+    if !file_type.is_file() && !file_type.is_dir() {
+        return None;
+    }
+    let file = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK | libc::O_NOFOLLOW)
+        .open(path)
+        .ok()?;
+    let mut flags: libc::c_int = 0;
+    let res = unsafe { libc::ioctl(file.as_raw_fd(), libc::FS_IOC_GETFLAGS, &mut flags) };
+
+    (res == 0).then_some(flags as u32)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "freebsd")))]
+pub fn file_flags(_path: &Path) -> Option<u32> {
+    None
+}
+
+/// フラグを "uchg,hidden" のようにカンマ区切りの名前にして返す。何もなければ "-"
+pub fn format_flags(flags: Option<u32>) -> String {
+    let names: Vec<_> = FLAG_NAMES
+        .iter()
+        .filter(|(bit, _)| flags.is_some_and(|f| f & bit != 0))
+        .map(|(_, name)| *name)
+        .collect();
+
+    if names.is_empty() {
+        "-".to_string()
+    } else {
+        names.join(",")
+    }
+}
+
+/// 不変 (i) や追記のみ (a) の属性の印を返す。何もなければ空文字列
+pub fn attr_indicator(flags: Option<u32>) -> String {
+    ATTR_INDICATORS
+        .iter()
+        .filter(|(bit, _)| flags.is_some_and(|f| f & bit != 0))
+        .map(|(_, c)| *c)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{attr_indicator, format_flags, ATTR_INDICATORS, FLAG_NAMES};
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_file_flags_skips_special_files() {
+        use std::{ffi::CString, fs, os::unix::fs::symlink};
+
+        use super::file_flags;
+
+        let dir = std::env::temp_dir().join(format!("lsr-flags-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let fifo = dir.join("fifo");
+        let c_fifo = CString::new(fifo.to_str().unwrap()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(c_fifo.as_ptr(), 0o644) }, 0);
+        symlink(&fifo, dir.join("link")).unwrap();
+
+        // FIFO、デバイス、シンボリックリンクは開かない
+        assert_eq!(file_flags(&fifo), None);
+        assert_eq!(file_flags(&dir.join("link")), None);
+        assert_eq!(file_flags(std::path::Path::new("/dev/null")), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_format_flags() {
+        assert_eq!(format_flags(None), "-");
+        assert_eq!(format_flags(Some(0)), "-");
+
+        let all = FLAG_NAMES.iter().fold(0, |acc, (bit, _)| acc | bit);
+        let names: Vec<_> = FLAG_NAMES.iter().map(|(_, name)| *name).collect();
+        assert_eq!(format_flags(Some(all)), names.join(","));
+    }
+
+    #[test]
+    fn test_attr_indicator() {
+        assert_eq!(attr_indicator(None), "");
+        assert_eq!(attr_indicator(Some(0)), "");
+
+        let all = ATTR_INDICATORS.iter().fold(0, |acc, (bit, _)| acc | bit);
+        let chars: String = ATTR_INDICATORS.iter().map(|(_, c)| *c).collect();
+        assert_eq!(attr_indicator(Some(all)), chars);
+    }
+}
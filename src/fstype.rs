@@ -0,0 +1,92 @@
+use std::{collections::HashMap, sync::OnceLock};
+
+/// /proc/self/mountinfo の内容から (major, minor, ファイルシステムの種類) の組を取り出す。
+/// 3 番目の欄がデバイス番号の "major:minor" で、"-" の欄の次が種類になる
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_mountinfo(info: &str) -> Vec<(u32, u32, String)> {
+    info.lines()
+        .filter_map(|l| {
+            let mut fields = l.split_whitespace();
+            let (major, minor) = fields.nth(2)?.split_once(':')?;
+            let fs_type = fields.skip_while(|&f| f != "-").nth(1)?;
+            Some((
+                major.parse().ok()?,
+                minor.parse().ok()?,
+                fs_type.to_string(),
+            ))
+        })
+        .collect()
+}
+
+/// マウントしているファイルシステムの (デバイス番号, 種類) の一覧を返す。
+/// マウントポイントは stat しないので、自動マウントや応答しない NFS に触れない
+#[cfg(target_os = "linux")]
+fn filesystems() -> Vec<(u64, String)> {
+    std::fs::read_to_string("/proc/self/mountinfo")
+        .map(|info| parse_mountinfo(&info))
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(major, minor, fs_type)| (libc::makedev(major, minor), fs_type))
+        .collect()
+}
+
+/// マウントしているファイルシステムの (デバイス番号, 種類) の一覧を返す。
+/// getmntinfo はデバイス番号を返さないので、マウントポイントを stat して調べる
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+fn filesystems() -> Vec<(u64, String)> {
+    use std::{ffi::CStr, os::unix::fs::MetadataExt};
+
+    let mut list: *mut libc::statfs = std::ptr::null_mut();
+    let count = unsafe { libc::getmntinfo(&mut list, libc::MNT_NOWAIT) };
+    if count <= 0 {
+        return vec![];
+    }
+    let list = unsafe { std::slice::from_raw_parts(list, count as usize) };
+    list.iter()
+        .filter_map(|fs| {
+            let (dir, fs_type) = unsafe {
+                (
+                    CStr::from_ptr(fs.f_mntonname.as_ptr()),
+                    CStr::from_ptr(fs.f_fstypename.as_ptr()),
+                )
+            };
+            let dev = std::fs::metadata(&*dir.to_string_lossy()).ok()?.dev();
+            Some((dev, fs_type.to_string_lossy().to_string()))
+        })
+        .collect()
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "freebsd")))]
+fn filesystems() -> Vec<(u64, String)> {
+    vec![]
+}
+
+/// デバイス番号 dev のファイルシステムの種類を返す。分からなければ "?"。
+/// マウントしているファイルシステムの一覧は最初の呼び出しで一度だけ作る
+pub fn fs_type(dev: u64) -> &'static str {
+    static TYPES: OnceLock<HashMap<u64, String>> = OnceLock::new();
+    // 同じデバイスが複数の場所にマウントされていても種類は同じなので、どれを残してもよい
+    let types = TYPES.get_or_init(|| filesystems().into_iter().collect());
+    types.get(&dev).map_or("?", |t| t.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_mountinfo;
+
+    #[test]
+    fn test_parse_mountinfo() {
+        // 省略可能な欄の数は行によって違い、マウントポイントの空白は \040 で書かれる
+        let info = "22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw\n\
+                    35 22 0:31 / /run/user/1000 rw - tmpfs tmpfs rw\n\
+                    40 22 259:3 / /mnt/my\\040disk rw shared:5 master:2 - xfs /dev/nvme0n1p3 rw\n";
+        assert_eq!(
+            parse_mountinfo(info),
+            [
+                (8, 1, "ext4".to_string()),
+                (0, 31, "tmpfs".to_string()),
+                (259, 3, "xfs".to_string()),
+            ]
+        );
+    }
+}
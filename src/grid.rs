@@ -0,0 +1,102 @@
+use unicode_width::UnicodeWidthStr;
+
+/// 列と列の間の空白の幅
+const COLUMN_GAP: usize = 2;
+
+/// SGR のエスケープシーケンスを除いた表示幅を返す
+fn visible_width(s: &str) -> usize {
+    let mut plain = String::with_capacity(s.len());
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // "\x1b[...m" の m までを読み飛ばす
+            for c in chars.by_ref() {
+                if c == 'm' {
+                    break;
+                }
+            }
+        } else {
+            plain.push(c);
+        }
+    }
+
+    plain.width()
+}
+
+/// ncols 列で横に並べたときの各列の幅 (最後の列以外は間の空白を含む)
+fn column_widths(widths: &[usize], ncols: usize) -> Vec<usize> {
+    let mut columns = vec![0; ncols];
+    for (i, width) in widths.iter().enumerate() {
+        let col = i % ncols;
+        let gap = if col == ncols - 1 { 0 } else { COLUMN_GAP };
+        columns[col] = columns[col].max(width + gap);
+    }
+    columns
+}
+
+/// cells を line_width に収まる最大の列数で左から右へ行ごとに並べた文字列を返す (ls -x)
+pub fn across(cells: &[String], line_width: usize) -> String {
+    if cells.is_empty() {
+        return String::new();
+    }
+
+    let widths: Vec<_> = cells.iter().map(|c| visible_width(c)).collect();
+    // 1 文字の名前と間の空白が並べられる列数より多くは試さない
+    let max_cols = (line_width / (1 + COLUMN_GAP)).clamp(1, cells.len());
+    let ncols = (1..=max_cols)
+        .rev()
+        // GNU ls と同じく、行の幅がちょうど line_width になる場合は収まらないものとする
+        .find(|&n| column_widths(&widths, n).iter().sum::<usize>() < line_width)
+        .unwrap_or(1);
+    let columns = column_widths(&widths, ncols);
+
+    let mut output = String::new();
+    for (row, chunk) in cells.chunks(ncols).enumerate() {
+        for (col, cell) in chunk.iter().enumerate() {
+            output.push_str(cell);
+            if col < chunk.len() - 1 {
+                let pad = columns[col] - widths[row * ncols + col];
+                output.push_str(&" ".repeat(pad));
+            }
+        }
+        output.push('\n');
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{across, visible_width};
+
+    #[test]
+    fn test_visible_width() {
+        assert_eq!(visible_width("fox.txt"), 7);
+        assert_eq!(visible_width("\x1b[01;34mdir\x1b[0m"), 3);
+        assert_eq!(visible_width("日本語"), 6);
+    }
+
+    #[test]
+    fn test_across() {
+        let cells: Vec<_> = ["a", "bbbb", "cc", "d", "eee"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        // 全部が 1 行に収まる
+        assert_eq!(across(&cells, 80), "a  bbbb  cc  d  eee\n");
+
+        // 3 列なら各列の最大幅に合わせて揃えられる
+        assert_eq!(across(&cells, 12), "a  bbbb  cc\nd  eee\n");
+
+        // 行の幅がちょうど line_width になる列数は使わない (ls -x -w 16 aaaa bbbb cccc)
+        let cells: Vec<_> = ["aaaa", "bbbb", "cccc"].map(String::from).to_vec();
+        assert_eq!(across(&cells, 16), "aaaa  bbbb\ncccc\n");
+        assert_eq!(across(&cells, 17), "aaaa  bbbb  cccc\n");
+
+        // どれだけ狭くても 1 列にはなる
+        let cells: Vec<_> = ["a", "bbbb", "cc", "d", "eee"].map(String::from).to_vec();
+        assert_eq!(across(&cells, 1), "a\nbbbb\ncc\nd\neee\n");
+    }
+}
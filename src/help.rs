@@ -0,0 +1,71 @@
+use clap::{Command, ValueEnum};
+
+use crate::json::{array, or_null, quote};
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HelpFormat {
+    Text,
+    Json,
+}
+
+/// cmd の引数の定義 (名前、別名、説明、既定値など) を JSON で返す
+pub fn help_json(cmd: &mut Command) -> String {
+    cmd.build();
+
+    let args: Vec<_> = cmd
+        .get_arguments()
+        .filter(|a| !a.is_hide_set())
+        .map(|a| {
+            let takes_value = a.get_action().takes_values();
+            let aliases = a.get_visible_aliases().unwrap_or_default();
+            let short_aliases: Vec<_> = a
+                .get_visible_short_aliases()
+                .unwrap_or_default()
+                .iter()
+                .map(|c| c.to_string())
+                .collect();
+            // フラグの既定値 ("false" など) とフラグ名から作られた値の名前は含めない
+            let defaults: Vec<_> = a
+                .get_default_values()
+                .iter()
+                .filter(|_| takes_value)
+                .map(|v| v.to_string_lossy())
+                .collect();
+            let possible_values: Vec<_> = a
+                .get_possible_values()
+                .iter()
+                .map(|v| v.get_name().to_string())
+                .collect();
+            let value_name = a
+                .get_value_names()
+                .and_then(|names| names.first())
+                .filter(|_| takes_value)
+                .map(|n| n.to_string());
+
+            format!(
+                "{{\"id\":{},\"long\":{},\"short\":{},\"aliases\":{},\"short_aliases\":{},\
+                 \"help\":{},\"positional\":{},\"takes_value\":{},\"value_name\":{},\
+                 \"default\":{},\"possible_values\":{}}}",
+                quote(a.get_id().as_str()),
+                or_null(a.get_long()),
+                or_null(a.get_short().map(|c| c.to_string())),
+                array(&aliases),
+                array(&short_aliases),
+                or_null(a.get_help().map(|h| h.to_string())),
+                a.is_positional(),
+                takes_value,
+                or_null(value_name),
+                array(&defaults),
+                array(&possible_values),
+            )
+        })
+        .collect();
+
+    format!(
+        "{{\"name\":{},\"version\":{},\"about\":{},\"args\":[{}]}}\n",
+        quote(cmd.get_name()),
+        or_null(cmd.get_version()),
+        or_null(cmd.get_about().map(|a| a.to_string())),
+        args.join(",")
+    )
+}
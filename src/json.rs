@@ -0,0 +1,112 @@
+use std::{
+    fs::{FileType, Metadata},
+    os::unix::fs::{FileTypeExt, MetadataExt},
+    path::Path,
+};
+
+use crate::format_mode;
+
+/// s を JSON の文字列リテラルとして引用符で囲み、特殊文字をエスケープして返す
+pub fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
+
+/// 文字列の一覧を JSON の配列にして返す
+pub fn array<S: AsRef<str>>(items: &[S]) -> String {
+    let items: Vec<_> = items.iter().map(|s| quote(s.as_ref())).collect();
+    format!("[{}]", items.join(","))
+}
+
+/// 文字列があれば引用符で囲み、なければ null を返す
+pub fn or_null<S: AsRef<str>>(value: Option<S>) -> String {
+    value.map_or_else(|| "null".to_string(), |s| quote(s.as_ref()))
+}
+
+/// ファイルの種別を表す名前を返す
+fn type_name(file_type: FileType) -> &'static str {
+    if file_type.is_symlink() {
+        "symlink"
+    } else if file_type.is_dir() {
+        "directory"
+    } else if file_type.is_fifo() {
+        "fifo"
+    } else if file_type.is_socket() {
+        "socket"
+    } else if file_type.is_block_device() {
+        "block_device"
+    } else if file_type.is_char_device() {
+        "char_device"
+    } else {
+        "file"
+    }
+}
+
+/// 1 つのエントリを JSON のオブジェクトにして返す
+pub fn entry(path: &Path, metadata: &Metadata) -> String {
+    let name = path
+        .file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy();
+    let file_type = path
+        .symlink_metadata()
+        .map_or(metadata.file_type(), |m| m.file_type());
+
+    format!(
+        "{{\"name\":{},\"path\":{},\"type\":{},\"mode\":{},\"nlink\":{},\"uid\":{},\"gid\":{},\
+         \"size\":{},\"modified\":{}}}",
+        quote(&name),
+        quote(&path.to_string_lossy()),
+        quote(type_name(file_type)),
+        quote(&format_mode(metadata.mode())),
+        metadata.nlink(),
+        metadata.uid(),
+        metadata.gid(),
+        metadata.size(),
+        metadata.mtime(),
+    )
+}
+
+/// ディレクトリの区切りの始まりを表す JSON のオブジェクトを返す
+pub fn directory(path: &Path, count: usize) -> String {
+    format!(
+        "{{\"directory\":{},\"entries\":{}}}",
+        quote(&path.to_string_lossy()),
+        count
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{array, or_null, quote};
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("fox.txt"), r#""fox.txt""#);
+        assert_eq!(quote("a \"b\"\\c\n"), r#""a \"b\"\\c\n""#);
+        assert_eq!(quote("\u{1b}"), r#""\u001b""#);
+    }
+
+    #[test]
+    fn test_array_and_null() {
+        assert_eq!(array(&["a", "b"]), r#"["a","b"]"#);
+        assert_eq!(array::<&str>(&[]), "[]");
+        assert_eq!(or_null(Some("l")), r#""l""#);
+        assert_eq!(or_null::<&str>(None), "null");
+    }
+}
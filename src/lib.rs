@@ -1,142 +1,2570 @@
+mod boot;
+mod color;
+mod complete;
+mod flags;
+mod fstype;
+mod grid;
+mod help;
+mod json;
 mod owner;
 
-use std::{fs, os::unix::fs::MetadataExt, path::PathBuf};
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    fmt::Write,
+    fs::{self, File, Metadata},
+    io::{self, Read},
+    os::unix::fs::{FileTypeExt, MetadataExt},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
 
 use chrono::{DateTime, Local};
-use clap::Parser;
+use clap::{
+    parser::ValueSource, value_parser, Arg, ArgAction, ArgMatches, CommandFactory, Parser,
+    ValueEnum,
+};
+use color::{
+    colorize, ensure_reset, gradient_code, legend, paint, size_scale_code, summary, truecolor,
+    ColorMap, ColorWhen,
+};
+use complete::{completions, Shell};
+use flags::{attr_indicator, file_flags, format_flags};
+use help::{help_json, HelpFormat};
 use owner::Owner;
 use tabular::{Row, Table};
-use users::{get_group_by_gid, get_user_by_uid};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+use users::{get_group_by_gid, get_group_by_name, get_user_by_name, get_user_by_uid};
 
 type MyResult<T> = Result<T, Box<dyn std::error::Error>>;
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[command(
     name = "lsr",
     version = "0.1.0",
     author = "Radish-Miyazaki <y.hidaka.kobe@gmail.com>",
-    about = "Rust ls"
+    about = "Rust ls",
+    disable_help_flag = true
 )]
 pub struct Args {
     #[arg(help = "Files and/or directories", default_value = ".")]
     paths: Vec<String>,
-    #[arg(help = "Long listing", short, long)]
-    long: bool,
+    #[command(flatten)]
+    format: OutputFormat,
     #[arg(help = "Show all files", short = 'a', long = "all")]
     show_hidden: bool,
+    #[arg(help = "Append / to directories", short = 'p')]
+    slash: bool,
+    #[arg(
+        help = "Append an indicator (one of */=@|) to entries",
+        short = 'F',
+        long
+    )]
+    classify: bool,
+    #[arg(
+        help = "Colorize the output",
+        long,
+        value_name = "WHEN",
+        value_enum,
+        default_value = "never",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "always"
+    )]
+    color: ColorWhen,
+    #[arg(
+        help = "Read the colors from a TOML theme FILE instead of LS_COLORS",
+        long,
+        value_name = "FILE"
+    )]
+    theme: Option<String>,
+    #[arg(
+        help = "Color sizes by the range they fall in between the given breaks",
+        long,
+        value_name = "SIZES",
+        value_delimiter = ',',
+        value_parser = parse_size
+    )]
+    size_scale_breaks: Vec<u64>,
+    #[arg(
+        help = "Shade sizes from green to red relative to the largest entry in the listing",
+        long
+    )]
+    color_scale: bool,
+    #[arg(
+        help = "Color files starting with \"#!\" as executables even without the execute bit",
+        long
+    )]
+    detect_scripts: bool,
+    #[arg(help = "Print the colors used for each file type and exit", long)]
+    color_legend: bool,
+    #[arg(
+        help = "Sort by WORD",
+        long,
+        value_name = "WORD",
+        value_enum,
+        default_value = "name",
+        overrides_with_all = ["sort_extension", "unsorted", "sort_size"]
+    )]
+    sort: SortKey,
+    #[arg(help = "Seed for --sort=random", long, value_name = "N")]
+    seed: Option<u64>,
+    #[arg(
+        help = "Sort alphabetically by entry extension",
+        short = 'X',
+        overrides_with_all = ["sort", "unsorted", "sort_size"]
+    )]
+    sort_extension: bool,
+    #[arg(
+        help = "Do not sort, same as --sort=none",
+        short = 'U',
+        overrides_with_all = ["sort", "sort_extension", "sort_size"]
+    )]
+    unsorted: bool,
+    #[arg(
+        help = "Sort by file size, largest first, same as --sort=size",
+        short = 'S',
+        overrides_with_all = ["sort", "sort_extension", "unsorted"]
+    )]
+    sort_size: bool,
+    #[arg(help = "List directories before files", long)]
+    group_directories_first: bool,
+    #[arg(
+        help = "List files before directories",
+        long,
+        conflicts_with = "group_directories_first"
+    )]
+    files_first: bool,
+    #[arg(help = "Ignore case when sorting", long)]
+    ignore_case: bool,
+    #[arg(
+        help = "Group digits of sizes, inodes and block counts with thousands separators",
+        long
+    )]
+    thousands_sep: bool,
+    #[arg(help = "List only symbolic links", long)]
+    only_symlinks: bool,
+    #[arg(
+        help = "Show every hop of symbolic links in long mode, like a -> b -> file",
+        long
+    )]
+    resolve_chain: bool,
+    #[arg(help = "List only entries with an execute bit set", long)]
+    executable: bool,
+    #[arg(help = "List only non-directory entries with an execute bit set", long)]
+    executable_files: bool,
+    #[arg(
+        help = "List only entries owned by user NAME (or numeric id)",
+        long,
+        value_name = "NAME"
+    )]
+    owner: Option<String>,
+    #[arg(
+        help = "List only entries owned by group NAME (or numeric id)",
+        long,
+        value_name = "NAME"
+    )]
+    group: Option<String>,
+    #[arg(
+        help = "List only entries owned by user id N",
+        long,
+        value_name = "N",
+        conflicts_with = "owner"
+    )]
+    uid: Option<u32>,
+    #[arg(
+        help = "List only entries owned by group id N",
+        long,
+        value_name = "N",
+        conflicts_with = "group"
+    )]
+    gid: Option<u32>,
+    #[arg(help = "List only entries modified since the system booted", long)]
+    since_boot: bool,
+    #[arg(
+        help = "List only entries modified after FILE",
+        long,
+        value_name = "FILE"
+    )]
+    newer: Option<String>,
+    #[arg(
+        help = "List only entries modified before FILE",
+        long,
+        value_name = "FILE"
+    )]
+    older: Option<String>,
+    #[arg(
+        help = "Do not list entries modified after FILE",
+        long,
+        value_name = "FILE"
+    )]
+    exclude_newer: Option<String>,
+    #[arg(
+        help = "Do not list entries modified before FILE",
+        long,
+        value_name = "FILE"
+    )]
+    exclude_older: Option<String>,
+    #[arg(
+        help = "List at most N entries of each directory",
+        long,
+        value_name = "N"
+    )]
+    max_entries: Option<usize>,
+    #[arg(help = "Stop listing after N entries in total", long, value_name = "N")]
+    max_entries_total: Option<usize>,
+    #[arg(
+        help = "Do not list entries whose name matches the shell PATTERN",
+        long,
+        value_name = "PATTERN"
+    )]
+    exclude: Vec<String>,
+    #[arg(
+        help = "Warn on stderr before listing a directory with more than N entries",
+        long,
+        value_name = "N"
+    )]
+    warn_large: Option<usize>,
+    #[arg(help = "List subdirectories recursively", short = 'R', long)]
+    recursive: bool,
+    #[arg(
+        help = "With -R, list subdirectories before the entries of their parent",
+        long,
+        requires = "recursive"
+    )]
+    depth_first_immediate: bool,
+    #[arg(
+        help = "With -R, descend into directories reached through symbolic links",
+        long,
+        requires = "recursive"
+    )]
+    follow_symlinks: bool,
+    #[arg(
+        help = "List the contents of directories given through symbolic link arguments",
+        long,
+        value_name = "BOOL",
+        default_value = "true",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        action = clap::ArgAction::Set
+    )]
+    follow_args_only: bool,
+    #[arg(
+        help = "With -R, omit subdirectories with no entries left to show",
+        long,
+        requires = "recursive"
+    )]
+    prune_empty: bool,
+    #[arg(
+        help = "With -R, prefix each entry with its depth like [2], counted as for --min-depth",
+        long,
+        requires = "recursive"
+    )]
+    show_depth: bool,
+    #[arg(
+        help = "With -R, do not list entries less than N levels below the arguments",
+        long,
+        value_name = "N",
+        default_value = "0",
+        requires = "recursive"
+    )]
+    min_depth: usize,
+    #[arg(
+        help = "Print the number of files and directories and their bytes after each directory",
+        long
+    )]
+    summary_per_dir: bool,
+    #[arg(
+        help = "Read NUL-terminated paths from FILE (\"-\" for stdin)",
+        long,
+        value_name = "FILE",
+        conflicts_with = "paths"
+    )]
+    files0_from: Option<String>,
+    #[arg(
+        help = "Assume the screen is COLS wide",
+        short = 'w',
+        long,
+        value_name = "COLS"
+    )]
+    width: Option<usize>,
+    #[arg(help = "Print the index number of each file", short = 'i', long)]
+    inode: bool,
+    #[arg(
+        help = "Show the device id of the file system containing each entry in long mode",
+        long
+    )]
+    device: bool,
+    #[arg(
+        help = "Show the type of the file system containing each entry in long mode",
+        long
+    )]
+    fstype: bool,
+    #[arg(
+        help = "Print the allocated size of each file, in blocks",
+        short = 's',
+        long
+    )]
+    size: bool,
+    #[arg(
+        help = "Count the blocks of -s in units of N bytes [default: 1024, 512 with --posix]",
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    blocks_size: Option<u64>,
+    #[arg(
+        help = "Show the allocated blocks in their own column before the size in long mode",
+        long
+    )]
+    blocks_column: bool,
+    #[arg(
+        help = "Print sizes in units of SIZE bytes, like 1K or 1M",
+        long,
+        value_name = "SIZE",
+        value_parser = parse_block_size
+    )]
+    block_size: Option<u64>,
+    #[arg(
+        help = "How to show the size of directories",
+        long,
+        value_name = "MODE",
+        value_enum,
+        default_value = "real"
+    )]
+    dir_size_display: DirSizeDisplay,
+    #[arg(
+        help = "Show the total size of the files under directories as their size",
+        long
+    )]
+    du: bool,
+    #[arg(
+        help = "Show each size as a percentage of the total size of the listing",
+        long
+    )]
+    relative_size: bool,
+    #[arg(
+        help = "Show the number of entries of directories instead of their size",
+        long
+    )]
+    dir_count: bool,
+    #[arg(
+        help = "Collapse regular files smaller than SIZE bytes into one summary line",
+        long,
+        value_name = "SIZE",
+        value_parser = parse_size
+    )]
+    collapse_below: Option<u64>,
+    #[arg(help = "Show the size of FIFOs and sockets as 0", long)]
+    normalize_special_sizes: bool,
+    #[arg(help = "Show file flags (chflags on BSD, chattr on Linux)", long)]
+    flags: bool,
+    #[arg(
+        help = "Append i (immutable) and a (append-only) to the mode on Linux",
+        long
+    )]
+    attrs: bool,
+    #[arg(help = "Show access, modification and change times", long)]
+    all_times: bool,
+    #[arg(
+        help = "Do not show the modification time",
+        long,
+        conflicts_with = "all_times"
+    )]
+    no_time: bool,
+    #[arg(
+        help = "Also show the birth time, or - where it is not available",
+        long,
+        visible_alias = "created"
+    )]
+    show_birth: bool,
+    #[arg(help = "Do not show the owner in long mode", long)]
+    no_user: bool,
+    #[arg(
+        help = "Truncate or pad names to N columns in long mode",
+        long,
+        value_name = "N"
+    )]
+    name_width: Option<usize>,
+    #[arg(
+        help = "Show the whole st_mode in octal, like 100644, in long mode",
+        long
+    )]
+    raw_mode: bool,
+    #[arg(
+        help = "Percent-encode bytes of names other than letters, digits and -._~/",
+        long
+    )]
+    uri_encode: bool,
+    #[arg(
+        help = "Lay out long mode exactly like coreutils ls -l",
+        long,
+        conflicts_with_all = ["no_user", "no_time", "time_style", "name_width", "show_hardlinks"]
+    )]
+    compat: bool,
+    #[arg(
+        help = "Show how long ago each entry was modified, like 3d or 2h",
+        long
+    )]
+    age_column: bool,
+    #[arg(
+        help = "Show how long ago each entry was accessed, modified and changed",
+        long
+    )]
+    time_deltas: bool,
+    #[arg(
+        help = "Mark entries that are hard links to the same file with a group number",
+        long
+    )]
+    show_hardlinks: bool,
+    #[arg(
+        help = "Sort entries by permissions and print a header before each permission set",
+        long
+    )]
+    group_by_perms: bool,
+    #[arg(
+        help = "Mark regular files with identical contents with a dup:N group (reads the files)",
+        long
+    )]
+    find_duplicates: bool,
+    #[arg(
+        help = "Pad inode, block and size numbers with zeros to the column width",
+        long
+    )]
+    pad_with_zeros: bool,
+    #[arg(
+        help = "Use POSIX defaults: 512-byte blocks and the traditional date format",
+        long
+    )]
+    posix: bool,
+    #[arg(
+        help = "How to show times",
+        long,
+        value_name = "STYLE",
+        value_enum,
+        default_value = "default"
+    )]
+    time_style: TimeStyle,
+    #[arg(help = "Print sizes like 1.2K and 15M", short = 'h', long)]
+    human_readable: bool,
+    #[arg(
+        help = "Unit labels used by -h",
+        long,
+        value_name = "STYLE",
+        value_enum,
+        default_value = "short"
+    )]
+    unit_style: UnitStyle,
+    #[arg(help = "List directories recursively as a tree", long)]
+    tree: bool,
+    #[arg(
+        help = "Descend at most N levels with --tree and print how many entries were shown",
+        long,
+        value_name = "N",
+        requires = "tree"
+    )]
+    max_depth: Option<usize>,
+    #[arg(
+        help = "Characters used to draw --tree",
+        long,
+        value_name = "STYLE",
+        value_enum,
+        default_value = "unicode"
+    )]
+    tree_style: TreeStyle,
+    #[arg(
+        help = "Print a JSON object mapping each directory to its entries",
+        long
+    )]
+    json_flat: bool,
+    #[arg(
+        help = "Print each directory and then its entries as one JSON object per line",
+        long,
+        conflicts_with = "json_flat"
+    )]
+    jsonl: bool,
+    #[arg(
+        help = "Write the listing of each directory to its own file in DIR (\"-\" for files)",
+        long,
+        value_name = "DIR"
+    )]
+    split_output: Option<String>,
+    #[arg(help = "Do not separate directory sections with blank lines", long)]
+    no_group_blank_lines: bool,
+    #[arg(
+        help = "Print help (\"json\" dumps the option schema)",
+        long,
+        value_name = "FORMAT",
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "text"
+    )]
+    help: Option<HelpFormat>,
+    #[arg(long, value_name = "SHELL", value_enum, hide = true)]
+    generate_completions: Option<Shell>,
 }
 
-fn find_files(paths: &[String], show_hidden: bool) -> MyResult<Vec<PathBuf>> {
-    let mut results = vec![];
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortKey {
+    None,
+    Name,
+    Extension,
+    Random,
+    /// 大きい順。-h で表示していてもバイト数で比べる
+    Size,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TreeStyle {
+    Unicode,
+    Ascii,
+}
+
+impl TreeStyle {
+    /// [途中の枝, 最後の枝, 続く枝の縦線, 空白] の順に罫線を返す
+    fn connectors(&self) -> [&'static str; 4] {
+        match self {
+            TreeStyle::Unicode => ["├── ", "└── ", "│   ", "    "],
+            TreeStyle::Ascii => ["|-- ", "`-- ", "|   ", "    "],
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DirSizeDisplay {
+    Real,
+    Zero,
+    Dash,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnitStyle {
+    /// K, M, G
+    Short,
+    /// KiB, MiB, GiB
+    Binary,
+    /// KB, MB, GB
+    Decimal,
+}
+
+impl UnitStyle {
+    /// human_size が返す "K" などの単位を、この形式の表記にする
+    fn label(&self, unit: &'static str) -> &'static str {
+        const SHORT: [&str; 6] = ["K", "M", "G", "T", "P", "E"];
+        const BINARY: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+        const DECIMAL: [&str; 6] = ["KB", "MB", "GB", "TB", "PB", "EB"];
+
+        let Some(i) = SHORT.iter().position(|u| *u == unit) else {
+            return unit;
+        };
+        match self {
+            UnitStyle::Short => SHORT[i],
+            UnitStyle::Binary => BINARY[i],
+            UnitStyle::Decimal => DECIMAL[i],
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeStyle {
+    /// 15:04
+    Default,
+    /// ISO 8601 の週日付 (2006-W01-1)
+    IsoWeek,
+    /// --posix の伝統的な形式
+    #[value(skip)]
+    Traditional,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TimeField {
+    Access,
+    Modify,
+    Change,
+    Birth,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Across,
+    Long,
+    #[value(alias = "single")]
+    SingleColumn,
+}
+
+/// -l と -x は --format=long と --format=across の短縮形として、同じ format を設定する。
+/// 3 つのうち最後に指定されたものが残る
+impl clap::Args for OutputFormat {
+    fn augment_args(cmd: clap::Command) -> clap::Command {
+        let shorthand = |id: &'static str, short, format: &'static str, help: &'static str| {
+            Arg::new(id)
+                .short(short)
+                .help(help)
+                .action(ArgAction::Set)
+                .num_args(0)
+                .default_missing_value(format)
+                .value_parser(value_parser!(OutputFormat))
+                .overrides_with_all(["format", "long", "across"])
+        };
+        cmd.arg(shorthand("long", 'l', "long", "Long listing").long("long"))
+            .arg(
+                Arg::new("format")
+                    .long("format")
+                    .help("Output format")
+                    .value_name("WORD")
+                    .value_parser(value_parser!(OutputFormat))
+                    .default_value("single-column")
+                    .overrides_with_all(["format", "long", "across"]),
+            )
+            .arg(shorthand(
+                "across",
+                'x',
+                "across",
+                "List entries by lines instead of by columns",
+            ))
+    }
+
+    fn augment_args_for_update(cmd: clap::Command) -> clap::Command {
+        Self::augment_args(cmd)
+    }
+}
+
+impl clap::FromArgMatches for OutputFormat {
+    fn from_arg_matches(matches: &ArgMatches) -> Result<Self, clap::Error> {
+        let mut format = OutputFormat::SingleColumn;
+        format.update_from_arg_matches(matches)?;
+        Ok(format)
+    }
+
+    fn update_from_arg_matches(&mut self, matches: &ArgMatches) -> Result<(), clap::Error> {
+        for id in ["long", "format", "across"] {
+            if matches.value_source(id) == Some(ValueSource::DefaultValue) {
+                continue;
+            }
+            if let Some(&format) = matches.get_one::<OutputFormat>(id) {
+                *self = format;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Args {
+    /// 出力の幅。--width がなければ環境変数 COLUMNS を使い、0 は無制限とする
+    fn line_width(&self) -> usize {
+        let width = self
+            .width
+            .or_else(|| std::env::var("COLUMNS").ok().and_then(|c| c.parse().ok()));
+        match width {
+            Some(0) => usize::MAX,
+            Some(w) => w,
+            None => 80,
+        }
+    }
+
+    /// サイズ列の単位。--block-size がなければ LS_BLOCK_SIZE、BLOCK_SIZE、BLOCKSIZE の順に
+    /// 環境変数を参照する。-h の場合は使わない
+    fn size_unit(&self) -> Option<u64> {
+        if self.human_readable {
+            return None;
+        }
+        self.block_size.or_else(|| {
+            ["LS_BLOCK_SIZE", "BLOCK_SIZE", "BLOCKSIZE"]
+                .iter()
+                .find_map(|name| parse_block_size(&std::env::var(name).ok()?).ok())
+        })
+    }
+
+    /// 時刻の形式。--time-style がなければ --posix で伝統的な形式にする
+    fn time_style(&self) -> TimeStyle {
+        match self.time_style {
+            TimeStyle::Default if self.posix => TimeStyle::Traditional,
+            style => style,
+        }
+    }
+
+    /// -s と --blocks-column で数えるブロックの大きさ
+    fn blocks_unit(&self) -> u64 {
+        self.blocks_size
+            .unwrap_or(if self.posix { 512 } else { 1024 })
+    }
+
+    /// -p と -F で名前の後に付ける種別の印
+    fn indicator(&self, path: &Path) -> &'static str {
+        if !self.slash && !self.classify {
+            return "";
+        }
+        let Ok(metadata) = path.symlink_metadata() else {
+            return "";
+        };
+        let file_type = metadata.file_type();
+
+        if file_type.is_dir() {
+            "/"
+        } else if !self.classify {
+            ""
+        } else if file_type.is_symlink() {
+            "@"
+        } else if file_type.is_fifo() {
+            "|"
+        } else if file_type.is_socket() {
+            "="
+        } else if is_executable(metadata.mode()) {
+            "*"
+        } else {
+            ""
+        }
+    }
+
+    fn sort_key(&self) -> SortKey {
+        if self.unsorted {
+            SortKey::None
+        } else if self.sort_size {
+            SortKey::Size
+        } else if self.sort_extension {
+            SortKey::Extension
+        } else {
+            self.sort
+        }
+    }
+}
+
+/// filename から NUL 区切りのパスの一覧を読み込む。"-" の場合は標準入力から読む
+fn read_files0(filename: &str) -> MyResult<Vec<String>> {
+    let mut buf = vec![];
+    match filename {
+        "-" => io::stdin().read_to_end(&mut buf)?,
+        _ => File::open(filename)
+            .map_err(|e| format!("{}: {}", filename, e))?
+            .read_to_end(&mut buf)?,
+    };
+
+    Ok(buf
+        .split(|&b| b == b'\0')
+        .filter(|p| !p.is_empty())
+        .map(|p| String::from_utf8_lossy(p).to_string())
+        .collect())
+}
+
+/// 一覧の 1 区切り。dir はディレクトリの見出しで、引数で直接与えられたファイルの場合は None。
+/// depth はエントリの深さで、--min-depth と同じく引数のファイルと引数のディレクトリの中を 0 とする
+#[derive(Debug)]
+struct Section {
+    dir: Option<PathBuf>,
+    depth: usize,
+    entries: Vec<PathBuf>,
+}
+
+/// dir の中のエントリを sort で並べ替えて返す
+fn read_entries(
+    dir: &Path,
+    show_hidden: bool,
+    sort: &dyn Fn(&mut [PathBuf]),
+) -> MyResult<Vec<PathBuf>> {
+    let mut entries = vec![];
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+
+        if show_hidden || !entry.file_name().to_string_lossy().starts_with('.') {
+            entries.push(entry.path());
+        }
+    }
+    sort(&mut entries);
+
+    Ok(entries)
+}
+
+/// ディレクトリを辿って区切りを集めるときの設定と、一覧全体での状態
+struct Walk<'a> {
+    show_hidden: bool,
+    recursive: bool,
+    sort: &'a dyn Fn(&mut [PathBuf]),
+    /// true の場合はディレクトリのエントリより先にサブディレクトリを辿る
+    immediate: bool,
+    /// true の場合はシンボリックリンク先のディレクトリも辿る
+    follow_symlinks: bool,
+    /// true の場合は引数のシンボリックリンク先のディレクトリを展開する。false ならリンクそのものを表示する
+    follow_args: bool,
+    /// 辿っている途中のディレクトリの (デバイス番号, inode 番号)
+    ancestors: Vec<(u64, u64)>,
+    /// 辿っている途中のディレクトリに戻るループを見つけて飛ばしたら true
+    cycles: bool,
+    /// この深さより浅いエントリは表示せずに辿るだけにする。引数のディレクトリの中が深さ 0
+    min_depth: usize,
+    /// true の場合は表示するエントリのないサブディレクトリの区切りを省く
+    prune_empty: bool,
+    /// 名前がいずれかに一致するエントリは表示も辿りもしない
+    exclude: &'a [String],
+    /// 表示するエントリなら true を返す
+    filter: &'a dyn Fn(&Path) -> bool,
+    /// エントリがこの数より多いディレクトリは一覧の前に警告する
+    warn_large: Option<usize>,
+    /// 1 つの区切りに表示するエントリの上限
+    max_entries: Option<usize>,
+    /// 一覧全体でまだ表示できるエントリの数
+    remaining: Option<usize>,
+    /// remaining の上限に達して表示しなかったエントリがあれば true
+    truncated: bool,
+    /// 中を展開した引数のディレクトリ
+    arg_dirs: Vec<PathBuf>,
+}
+
+impl<'a> Walk<'a> {
+    fn new(show_hidden: bool, recursive: bool, sort: &'a dyn Fn(&mut [PathBuf])) -> Self {
+        Walk {
+            show_hidden,
+            recursive,
+            sort,
+            immediate: false,
+            follow_symlinks: false,
+            follow_args: true,
+            ancestors: vec![],
+            cycles: false,
+            min_depth: 0,
+            prune_empty: false,
+            exclude: &[],
+            filter: &|_| true,
+            warn_large: None,
+            max_entries: None,
+            remaining: None,
+            truncated: false,
+            arg_dirs: vec![],
+        }
+    }
+
+    fn exhausted(&self) -> bool {
+        self.remaining == Some(0)
+    }
+
+    /// entries から表示するものを選び、上限を超えた分を切り捨てる
+    fn select(&mut self, entries: &[PathBuf]) -> Vec<PathBuf> {
+        let mut shown: Vec<_> = entries
+            .iter()
+            .filter(|p| (self.filter)(p))
+            .cloned()
+            .collect();
+        if let Some(max) = self.max_entries {
+            shown.truncate(max);
+        }
+        if let Some(remaining) = self.remaining.as_mut() {
+            if shown.len() > *remaining {
+                shown.truncate(*remaining);
+                self.truncated = true;
+            }
+            *remaining -= shown.len();
+        }
+        shown
+    }
+
+    fn push_section(
+        &mut self,
+        dir: &Path,
+        depth: usize,
+        entries: &[PathBuf],
+        sections: &mut Vec<Section>,
+    ) {
+        if depth < self.min_depth {
+            return;
+        }
+        let entries = self.select(entries);
+        // 引数で与えたディレクトリは空でも見出しを残す
+        if self.prune_empty && depth > 0 && entries.is_empty() {
+            return;
+        }
+        sections.push(Section {
+            dir: Some(dir.to_path_buf()),
+            depth,
+            entries,
+        });
+    }
+
+    /// dir の中のエントリを sections に追加する。recursive が true の場合はサブディレクトリも辿る
+    fn read_section(
+        &mut self,
+        dir: &Path,
+        depth: usize,
+        sections: &mut Vec<Section>,
+    ) -> MyResult<()> {
+        // 辿っている途中のディレクトリと同じものに戻った場合はループなので飛ばす
+        let id = fs::metadata(dir).ok().map(|m| (m.dev(), m.ino()));
+        if let Some(id) = id {
+            if self.ancestors.contains(&id) {
+                eprintln!(
+                    "lsr: {}: not listing already-listed directory",
+                    dir.display()
+                );
+                self.cycles = true;
+                return Ok(());
+            }
+            self.ancestors.push(id);
+        }
+        let result = self.read_dir_section(dir, depth, sections);
+        if id.is_some() {
+            self.ancestors.pop();
+        }
+        result
+    }
+
+    fn read_dir_section(
+        &mut self,
+        dir: &Path,
+        depth: usize,
+        sections: &mut Vec<Section>,
+    ) -> MyResult<()> {
+        // 読めないディレクトリは見出しだけを残し、エラーを報告して残りを続ける
+        let mut entries = match read_entries(dir, self.show_hidden, self.sort) {
+            Ok(entries) => entries,
+            Err(e) => {
+                eprintln!("{}: {}", dir.display(), e);
+                if depth >= self.min_depth {
+                    sections.push(Section {
+                        dir: Some(dir.to_path_buf()),
+                        depth,
+                        entries: vec![],
+                    });
+                }
+                return Ok(());
+            }
+        };
+        entries.retain(|p| {
+            let name = p.file_name().unwrap_or_default().to_string_lossy();
+            !self
+                .exclude
+                .iter()
+                .any(|pattern| matches_pattern(pattern, &name))
+        });
+        if self.warn_large.is_some_and(|n| entries.len() > n) {
+            eprintln!("lsr: {}: listing {} entries", dir.display(), entries.len());
+        }
+
+        // read_dir は "." と ".." を返さないので、辿るのは実際の子ディレクトリだけになる。
+        // シンボリックリンク先のディレクトリは follow_symlinks の場合だけ辿る
+        let subdirs: Vec<_> = entries
+            .iter()
+            .filter(|p| {
+                let metadata = if self.follow_symlinks {
+                    p.metadata()
+                } else {
+                    p.symlink_metadata()
+                };
+                self.recursive && metadata.is_ok_and(|m| m.is_dir())
+            })
+            .cloned()
+            .collect();
+
+        if !self.immediate {
+            self.push_section(dir, depth, &entries, sections);
+        }
+
+        for subdir in subdirs {
+            // 全体の上限に達したらそれ以上辿らない
+            if self.exhausted() {
+                self.truncated = true;
+                break;
+            }
+            self.read_section(&subdir, depth + 1, sections)?;
+        }
+
+        if self.immediate {
+            self.push_section(dir, depth, &entries, sections);
+        }
+
+        Ok(())
+    }
+
+    /// paths を見出しごとの区切りに分けて返す。ファイルの引数は並べ替えて先頭の見出しのない区切りに
+    /// まとめ、ディレクトリの引数はその後に引数の順で展開する
+    fn find_sections(&mut self, paths: &[String]) -> MyResult<Vec<Section>> {
+        let mut files = vec![];
+        let mut dirs = vec![];
+
+        for path in paths {
+            match fs::metadata(path) {
+                Err(e) => {
+                    eprintln!("{}: {}", path, e);
+                }
+                Ok(m) => {
+                    let link = !self.follow_args && Path::new(path).is_symlink();
+                    // FIFO やデバイスなどディレクトリ以外はファイルと同じく先頭の区切りに入れる
+                    if !m.is_dir() || link {
+                        files.push(PathBuf::from(path));
+                    } else {
+                        dirs.push(path);
+                    }
+                }
+            }
+        }
+
+        self.arg_dirs = dirs.iter().map(PathBuf::from).collect();
+        let mut sections = vec![];
+        // ファイルの引数は深さ 0 として扱う。並べ替えない場合は引数の順のままになり、
+        // ディレクトリの中身は read_dir が返した順になる
+        if !files.is_empty() && self.min_depth == 0 {
+            (self.sort)(&mut files);
+            let entries = self.select(&files);
+            sections.push(Section {
+                dir: None,
+                depth: 0,
+                entries,
+            });
+        }
+
+        for dir in dirs {
+            if self.exhausted() {
+                self.truncated = true;
+                break;
+            }
+            self.read_section(Path::new(dir), 0, &mut sections)?;
+        }
+
+        Ok(sections)
+    }
+}
+
+/// name がシェルのワイルドカード pattern (*, ?, [...]) に一致すれば true を返す
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    fn matches(pattern: &[char], name: &[char]) -> bool {
+        match pattern.split_first() {
+            None => name.is_empty(),
+            Some(('*', rest)) => (0..=name.len()).any(|i| matches(rest, &name[i..])),
+            Some(('?', rest)) => !name.is_empty() && matches(rest, &name[1..]),
+            Some(('[', rest)) => {
+                let Some(end) = rest.iter().skip(1).position(|&c| c == ']').map(|i| i + 1) else {
+                    // 閉じていない [ は文字そのものとして扱う
+                    return name.first() == Some(&'[') && matches(rest, &name[1..]);
+                };
+                let Some(&c) = name.first() else {
+                    return false;
+                };
+                let (negate, set) = match rest[..end].split_first() {
+                    Some(('!' | '^', set)) => (true, set),
+                    _ => (false, &rest[..end]),
+                };
+                let mut found = false;
+                let mut i = 0;
+                while i < set.len() {
+                    if i + 2 < set.len() && set[i + 1] == '-' {
+                        found |= set[i] <= c && c <= set[i + 2];
+                        i += 3;
+                    } else {
+                        found |= set[i] == c;
+                        i += 1;
+                    }
+                }
+                found != negate && matches(&rest[end + 1..], &name[1..])
+            }
+            Some((&p, rest)) => name.first() == Some(&p) && matches(rest, &name[1..]),
+        }
+    }
+
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    matches(&pattern, &name)
+}
+
+/// paths に含まれるファイルとディレクトリのエントリを、見出しで区切らずに並べて返す。
+/// discover で見つけたエントリを、区切りごとに名前順に並べる
+pub fn find_files(paths: &[String], show_hidden: bool, recursive: bool) -> MyResult<Vec<PathBuf>> {
+    let options = DiscoverOptions {
+        show_hidden,
+        recursive,
+    };
+    let mut entries = discover(paths, options)?;
+    for section in entries.chunk_by_mut(|a, b| a.dir == b.dir) {
+        section.sort_by(|a, b| a.path.cmp(&b.path));
+    }
+    Ok(entries.into_iter().map(|e| e.path).collect())
+}
+
+/// discover でエントリを探すときの設定
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DiscoverOptions {
+    /// "." で始まるエントリも返す
+    pub show_hidden: bool,
+    /// サブディレクトリも辿る
+    pub recursive: bool,
+}
+
+/// discover が見つけたエントリ。dir は中身として見つけたディレクトリで、引数で直接与えられたファイルの場合は None
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub dir: Option<PathBuf>,
+}
+
+/// paths に含まれるエントリを並べ替えずに返す。ファイルの引数は引数の順、ディレクトリの中身は
+/// read_dir(3) が返した順になるので、呼び出し側で好きな順に並べ替えられる
+pub fn discover(paths: &[String], options: DiscoverOptions) -> MyResult<Vec<Entry>> {
+    let sections =
+        Walk::new(options.show_hidden, options.recursive, &|_| {}).find_sections(paths)?;
+    Ok(sections
+        .into_iter()
+        .flat_map(|s| {
+            let dir = s.dir;
+            s.entries.into_iter().map(move |path| Entry {
+                path,
+                dir: dir.clone(),
+            })
+        })
+        .collect())
+}
+
+/// ユーザー名または数値の ID から uid を返す
+fn resolve_uid(name: &str) -> MyResult<u32> {
+    match name.parse() {
+        Ok(uid) => Ok(uid),
+        Err(_) => get_user_by_name(name)
+            .map(|u| u.uid())
+            .ok_or_else(|| format!("{}: no such user", name).into()),
+    }
+}
+
+/// グループ名または数値の ID から gid を返す
+fn resolve_gid(name: &str) -> MyResult<u32> {
+    match name.parse() {
+        Ok(gid) => Ok(gid),
+        Err(_) => get_group_by_name(name)
+            .map(|g| g.gid())
+            .ok_or_else(|| format!("{}: no such group", name).into()),
+    }
+}
+
+/// --newer などの基準になるファイルの更新時刻を返す
+fn reference_time(file: &str) -> MyResult<SystemTime> {
+    fs::metadata(file)
+        .and_then(|m| m.modified())
+        .map_err(|e| format!("{}: {}", file, e).into())
+}
+
+/// metadata の更新時刻が time 以降なら true を返す
+fn modified_since(metadata: &Metadata, time: SystemTime) -> bool {
+    metadata.modified().is_ok_and(|t| t >= time)
+}
+
+/// seed から決まる順にパスを並べ替える (splitmix64 による Fisher-Yates シャッフル)
+fn shuffle(paths: &mut [PathBuf], seed: u64) {
+    let mut state = seed;
+    let mut next = || {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+
+    for i in (1..paths.len()).rev() {
+        let j = (next() % (i as u64 + 1)) as usize;
+        paths.swap(i, j);
+    }
+}
+
+/// --seed がない場合のシャッフルの種。時刻とプロセス ID から作る
+fn random_seed() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64);
+    nanos ^ (std::process::id() as u64).rotate_left(32)
+}
+
+/// ディレクトリとそれ以外に分ける。dirs_first が true ならディレクトリを、false ならそれ以外を先にする。
+/// 安定ソートなので、それぞれの中の並び順は保つ
+fn group_dirs(paths: &mut [PathBuf], dirs_first: bool) {
+    paths.sort_by_key(|p| p.is_dir() != dirs_first);
+}
+
+/// key に従ってパスを並べ替える。ignore_case が true の場合は大文字・小文字を区別しない。
+/// seed は key が random の場合に使う
+fn sort_paths(paths: &mut [PathBuf], key: SortKey, ignore_case: bool, seed: u64) {
+    let fold = |s: &str| {
+        if ignore_case {
+            s.to_lowercase()
+        } else {
+            s.to_string()
+        }
+    };
+    let name = |p: &PathBuf| fold(&p.to_string_lossy());
+    let extension = |p: &PathBuf| fold(&p.extension().unwrap_or_default().to_string_lossy());
+
+    match key {
+        SortKey::None => {}
+        SortKey::Name => paths.sort_by_cached_key(name),
+        SortKey::Extension => paths.sort_by_cached_key(|p| (extension(p), name(p))),
+        // 表示用の文字列 (1.2K など) ではなく st_size の数値で比べる。
+        // 長い形式のサイズ列と同じく、シンボリックリンクはリンク先の大きさを使う
+        SortKey::Size => paths.sort_by_cached_key(|p| {
+            let size = entry_metadata(p).map_or(0, |m| m.size());
+            (std::cmp::Reverse(size), name(p))
+        }),
+        SortKey::Random => {
+            // 同じ種なら読み込んだ順によらず同じ並びになるように、名前順にしてからシャッフルする
+            paths.sort_by_cached_key(name);
+            shuffle(paths, seed);
+        }
+    }
+}
+
+pub fn mk_triple(mode: u32, owner: Owner) -> String {
+    let [read, write, execute] = owner.masks();
+    format!(
+        "{}{}{}",
+        if mode & read == 0 { '-' } else { 'r' },
+        if mode & write == 0 { '-' } else { 'w' },
+        if mode & execute == 0 { '-' } else { 'x' }
+    )
+}
+
+/// 所有者・グループ・その他のいずれかの実行ビットが立っていれば true を返す
+fn is_executable(mode: u32) -> bool {
+    [Owner::User, Owner::Group, Owner::Other]
+        .iter()
+        .any(|owner| mode & owner.masks()[2] != 0)
+}
+
+/// 0o751 のような 8 進数でファイルモードを指定すると、
+/// "rwrr-x--x" のような文字列を返す
+fn format_mode(mode: u32) -> String {
+    format!(
+        "{}{}{}",
+        mk_triple(mode, Owner::User),
+        mk_triple(mode, Owner::Group),
+        mk_triple(mode, Owner::Other)
+    )
+}
+
+/// path のバイト列のうち英数字と - . _ ~ / 以外を %XX の形にして返す
+fn uri_encode(path: &Path) -> String {
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut encoded = String::new();
+    for &b in path.as_os_str().as_bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~/".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+
+/// 色を付ける前の表示用のパス名と種別の印を返す
+fn display_name(path: &Path, args: &Args) -> String {
+    let name = if args.uri_encode {
+        uri_encode(path)
+    } else {
+        path.display().to_string()
+    };
+    format!("{}{}", name, args.indicator(path))
+}
+
+/// --show-depth の場合に名前の前に付ける "[2] " のような深さ
+fn depth_prefix(depth: Option<usize>) -> String {
+    depth.map_or(String::new(), |depth| format!("[{}] ", depth))
+}
+
+/// 表示用のパス名を返す。colors が与えられた場合は種別ごとに色付けする
+fn render_name(path: &Path, args: &Args, colors: Option<&ColorMap>) -> String {
+    // 末尾の印も名前と同じ色にする
+    let name = display_name(path, args);
+    match colors {
+        Some(map) => colorize(path, &name, map),
+        None => name,
+    }
+}
+
+/// name を表示幅 width に合わせる。長ければ末尾を "…" にして切り詰め、短ければ空白で埋める。
+/// 書記素クラスタ (結合文字や ZWJ、国旗の地域指示子の組) の途中では切らない
+fn fit_width(name: &str, width: usize) -> String {
+    if name.width() <= width {
+        return name.to_string() + &" ".repeat(width - name.width());
+    }
+
+    let mut fitted = String::new();
+    for cluster in name.graphemes(true) {
+        if fitted.width() + cluster.width() + 1 > width {
+            break;
+        }
+        fitted.push_str(cluster);
+    }
+    if width > 0 {
+        fitted.push('…');
+    }
+    let padding = width.saturating_sub(fitted.width());
+    fitted + &" ".repeat(padding)
+}
+
+/// 長い形式の名前の列を返す。--name-width の場合は色を付ける前に幅をそろえる
+fn render_long_name(path: &Path, args: &Args, colors: Option<&ColorMap>) -> String {
+    let Some(width) = args.name_width else {
+        return render_name(path, args, colors);
+    };
+    let name = fit_width(&display_name(path, args), width);
+    match colors {
+        Some(map) => colorize(path, &name, map),
+        None => name,
+    }
+}
+
+/// 1234567 のような数値を "1,234,567" のように 3 桁ごとに区切った文字列を返す
+fn group_thousands(n: u64) -> String {
+    let digits = n.to_string();
+    let mut grouped = String::new();
+
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+
+    grouped
+}
+
+/// path のメタデータを返す。リンク切れのシンボリックリンクはリンク自体の情報を返す
+fn entry_metadata(path: &Path) -> io::Result<Metadata> {
+    path.metadata().or_else(|_| path.symlink_metadata())
+}
+
+/// 512 バイト単位の st_blocks を unit バイト単位に換算する (端数は切り上げ)
+fn count_blocks(metadata: &Metadata, unit: u64) -> u64 {
+    (metadata.blocks() * 512).div_ceil(unit)
+}
+
+/// 割り当てられた bytes バイトを coreutils と同じく、-h なら 12K のような形に、--block-size などで
+/// サイズ列の単位があればその単位に、なければ -s と同じブロックの単位に換算する
+fn format_block_bytes(bytes: u64, args: &Args) -> String {
+    if args.human_readable {
+        let (number, unit) = human_size(bytes);
+        return number + unit;
+    }
+    let unit = args.size_unit().unwrap_or_else(|| args.blocks_unit());
+    bytes.div_ceil(unit).to_string()
+}
+
+/// 長い形式のディレクトリの区切りの先頭に付ける "total 12" の行を返す。それ以外の区切りでは空にする。
+/// エントリの st_blocks の合計を format_block_bytes で換算する
+fn total_line(section: &Section, args: &Args, colors: Option<&ColorMap>) -> String {
+    if section.dir.is_none() || args.format != OutputFormat::Long {
+        return String::new();
+    }
+    let blocks: u64 = section
+        .entries
+        .iter()
+        .filter_map(|p| fs::symlink_metadata(p).ok())
+        .map(|m| m.blocks())
+        .sum();
+    let line = format!("total {}", format_block_bytes(blocks * 512, args));
+    summary(&line, colors) + "\n"
+}
+
+/// metadata から field で指定した時刻を取り出す
+fn entry_time(metadata: &Metadata, field: TimeField) -> MyResult<DateTime<Local>> {
+    let time = match field {
+        TimeField::Access => DateTime::from(metadata.accessed()?),
+        TimeField::Modify => DateTime::from(metadata.modified()?),
+        TimeField::Change => {
+            DateTime::from_timestamp(metadata.ctime(), metadata.ctime_nsec() as u32)
+                .ok_or("invalid change time")?
+                .with_timezone(&Local)
+        }
+        // Linux では statx の btime、macOS と BSD では st_birthtime を使う
+        TimeField::Birth => DateTime::from(metadata.created()?),
+    };
+
+    Ok(time)
+}
+
+/// 1024 を基数として、1.2K や 15M のような数値と単位の組を返す (GNU ls と同様に切り上げる)
+fn human_size(bytes: u64) -> (String, &'static str) {
+    const UNITS: [&str; 6] = ["K", "M", "G", "T", "P", "E"];
+
+    if bytes < 1024 {
+        return (bytes.to_string(), "");
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    loop {
+        let rounded = if size < 10.0 {
+            (size * 10.0).ceil() / 10.0
+        } else {
+            size.ceil()
+        };
+
+        // 切り上げた結果が 1024 に達した場合は次の単位に繰り上げる
+        if rounded < 1024.0 || unit == UNITS.len() - 1 {
+            let number = if rounded < 10.0 {
+                format!("{:.1}", rounded)
+            } else {
+                format!("{}", rounded)
+            };
+            return (number, UNITS[unit]);
+        }
+
+        size /= 1024.0;
+        unit += 1;
+    }
+}
+
+/// "1K" や "15M" のように 1024 を基数とする単位の付いたサイズをバイト数にする
+fn parse_size(s: &str) -> Result<u64, String> {
+    const UNITS: [char; 6] = ['K', 'M', 'G', 'T', 'P', 'E'];
+
+    let s = s.trim();
+    let (number, exp) = match s.chars().last().and_then(|c| {
+        UNITS
+            .iter()
+            .position(|&u| u == c.to_ascii_uppercase())
+            .map(|i| i as u32 + 1)
+    }) {
+        Some(exp) => (&s[..s.len() - 1], exp),
+        None => (s, 0),
+    };
+
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(1024u64.pow(exp)))
+        .ok_or_else(|| format!("invalid size: {}", s))
+}
+
+/// 0 以外のブロックサイズを解析する
+fn parse_block_size(s: &str) -> Result<u64, String> {
+    match parse_size(s)? {
+        0 => Err(format!("invalid block size: {}", s)),
+        size => Ok(size),
+    }
+}
+
+/// path の下にあるファイルのサイズの合計を返す。シンボリックリンクは辿らない
+fn disk_usage(path: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .filter_map(Result::ok)
+        .map(|e| match e.metadata() {
+            Ok(m) if m.is_dir() => disk_usage(&e.path()),
+            Ok(m) => m.size(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+/// サイズ列に表示するバイト数を返す。--du の場合はディレクトリの下の合計にする
+fn entry_size(path: &Path, metadata: &Metadata, args: &Args) -> u64 {
+    if args.du && metadata.is_dir() {
+        disk_usage(path)
+    } else {
+        metadata.size()
+    }
+}
+
+/// 各エントリのサイズが一覧全体の合計に占める割合を "12.5%" のように返す。
+/// --du がなければディレクトリは合計に含めず "-" にする
+fn relative_sizes(paths: &[PathBuf], metadatas: &[Metadata], args: &Args) -> Vec<String> {
+    let sizes: Vec<_> = paths
+        .iter()
+        .zip(metadatas)
+        .map(|(p, m)| (args.du || !m.is_dir()).then(|| entry_size(p, m, args)))
+        .collect();
+    let total = sizes.iter().flatten().sum::<u64>().max(1);
+
+    sizes
+        .iter()
+        .map(|size| match size {
+            Some(size) => format!("{:.1}%", *size as f64 * 100.0 / total as f64),
+            None => "-".to_string(),
+        })
+        .collect()
+}
+
+/// サイズ列に表示する数値と単位の組を返す
+fn size_fields(
+    paths: &[PathBuf],
+    metadatas: &[Metadata],
+    args: &Args,
+) -> Vec<(String, &'static str)> {
+    let unit = args.size_unit().unwrap_or(1);
+    paths
+        .iter()
+        .zip(metadatas)
+        .map(|(p, m)| {
+            if args.dir_count && m.is_dir() {
+                // -a がなければ隠しファイルは数えない
+                match fs::read_dir(p) {
+                    Ok(entries) => {
+                        let count = entries
+                            .filter_map(Result::ok)
+                            .filter(|e| {
+                                args.show_hidden
+                                    || !e.file_name().to_string_lossy().starts_with('.')
+                            })
+                            .count();
+                        (count.to_string(), "")
+                    }
+                    Err(_) => ("?".to_string(), ""),
+                }
+            } else if args.normalize_special_sizes
+                && (m.file_type().is_fifo() || m.file_type().is_socket())
+                || m.is_dir() && args.dir_size_display == DirSizeDisplay::Zero
+            {
+                ("0".to_string(), "")
+            } else if m.is_dir() && args.dir_size_display == DirSizeDisplay::Dash {
+                ("-".to_string(), "")
+            } else if args.human_readable {
+                let (number, unit) = human_size(entry_size(p, m, args));
+                (number, args.unit_style.label(unit))
+            } else if args.thousands_sep {
+                (group_thousands(entry_size(p, m, args).div_ceil(unit)), "")
+            } else {
+                (entry_size(p, m, args).div_ceil(unit).to_string(), "")
+            }
+        })
+        .collect()
+}
+
+/// サイズ列の文字列を返す。-h の場合は小数点と単位の位置をそろえる
+fn format_sizes(paths: &[PathBuf], metadatas: &[Metadata], args: &Args) -> Vec<String> {
+    let sizes = size_fields(paths, metadatas, args);
+    let frac_width = sizes
+        .iter()
+        .map(|(n, _)| n.find('.').map_or(0, |i| n.len() - i))
+        .max()
+        .unwrap_or(0);
+    let unit_width = sizes.iter().map(|(_, u)| u.len()).max().unwrap_or(0);
+
+    // 小数点の位置と単位の列がそろうように、小数部と単位をそれぞれの幅まで空白で埋める
+    sizes
+        .into_iter()
+        .map(|(n, u)| {
+            let (int, frac) = n.split_at(n.find('.').unwrap_or(n.len()));
+            format!(
+                "{}{:<fw$}{:<uw$}",
+                int,
+                frac,
+                u,
+                fw = frac_width,
+                uw = unit_width
+            )
+        })
+        .collect()
+}
+
+/// シンボリックリンク path を辿った " -> b -> c" のような文字列を返す。
+/// ループしている場合と辿りすぎた場合は末尾に " [cycle]" を付ける
+fn symlink_chain(path: &Path) -> String {
+    // Linux の SYMLOOP_MAX にそろえる
+    const MAX_HOPS: usize = 40;
+
+    let mut chain = String::new();
+    let mut seen = vec![path.to_path_buf()];
+    let mut current = path.to_path_buf();
+    while let Ok(target) = fs::read_link(&current) {
+        chain.push_str(&format!(" -> {}", target.display()));
+        // 相対パスのリンク先はリンクのあるディレクトリから辿る
+        current = current.parent().unwrap_or(Path::new("")).join(&target);
+        if seen.contains(&current) || seen.len() > MAX_HOPS {
+            chain.push_str(" [cycle]");
+            break;
+        }
+        seen.push(current.clone());
+    }
+
+    chain
+}
+
+/// 一覧の中で同じ (デバイス, inode) を持つエントリに、現れた順に 1 から振ったグループ番号を返す。
+/// シンボリックリンクはリンク先ではなくリンクそのものの inode で比べる
+fn hardlink_groups(paths: &[PathBuf]) -> Vec<Option<usize>> {
+    let keys: Vec<_> = paths
+        .iter()
+        .map(|p| fs::symlink_metadata(p).ok().map(|m| (m.dev(), m.ino())))
+        .collect();
+    let mut counts: HashMap<(u64, u64), usize> = HashMap::new();
+    for key in keys.iter().flatten() {
+        *counts.entry(*key).or_default() += 1;
+    }
+
+    let mut groups = HashMap::new();
+    keys.iter()
+        .map(|key| {
+            let key = (*key)?;
+            if counts[&key] < 2 {
+                return None;
+            }
+            let next = groups.len() + 1;
+            Some(*groups.entry(key).or_insert(next))
+        })
+        .collect()
+}
+
+/// ファイルの中身を少しずつ読んでハッシュ値を返す
+fn content_hash(path: &Path) -> io::Result<u64> {
+    use std::hash::{DefaultHasher, Hasher};
+
+    let mut file = File::open(path)?;
+    let mut hasher = DefaultHasher::new();
+    let mut buf = [0; 8192];
+    loop {
+        match file.read(&mut buf)? {
+            0 => return Ok(hasher.finish()),
+            n => hasher.write(&buf[..n]),
+        }
+    }
+}
+
+/// file から buf がいっぱいになるか終わりに達するまで読み、読んだバイト数を返す
+fn read_chunk(file: &mut File, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match file.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+/// 2 つのファイルの中身を先頭から比べ、すべてのバイトが同じなら true を返す
+fn same_contents(a: &Path, b: &Path) -> io::Result<bool> {
+    let (mut a, mut b) = (File::open(a)?, File::open(b)?);
+    let (mut buf_a, mut buf_b) = ([0; 8192], [0; 8192]);
+    loop {
+        let n = read_chunk(&mut a, &mut buf_a)?;
+        let m = read_chunk(&mut b, &mut buf_b)?;
+        if buf_a[..n] != buf_b[..m] {
+            return Ok(false);
+        }
+        if n == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// 一覧の中で中身が同じ通常ファイルに、現れた順に 1 から振ったグループ番号を返す。
+/// 同じサイズのファイルが他にあるものだけを読み、ハッシュ値も同じものは中身を 1 バイトずつ比べる。
+/// シンボリックリンクと、前に現れたファイルのハードリンクは別のコピーではないので数えない
+fn duplicate_groups(paths: &[PathBuf]) -> Vec<Option<usize>> {
+    let mut inodes = HashSet::new();
+    let metadatas: Vec<_> = paths
+        .iter()
+        .map(|p| {
+            fs::symlink_metadata(p)
+                .ok()
+                .filter(|m| m.is_file() && inodes.insert((m.dev(), m.ino())))
+        })
+        .collect();
+    let mut sizes: HashMap<u64, usize> = HashMap::new();
+    for m in metadatas.iter().flatten() {
+        *sizes.entry(m.size()).or_default() += 1;
+    }
+
+    // 中身が同じファイルのまとまりの (最初のファイル, ファイルの数) と、サイズとハッシュ値ごとのまとまり
+    let mut classes: Vec<(&Path, usize)> = vec![];
+    let mut buckets: HashMap<(u64, u64), Vec<usize>> = HashMap::new();
+    let members: Vec<_> = paths
+        .iter()
+        .zip(&metadatas)
+        .map(|(p, m)| {
+            let m = m.as_ref().filter(|m| sizes[&m.size()] >= 2)?;
+            let key = (m.size(), content_hash(p).ok()?);
+            let bucket = buckets.entry(key).or_default();
+            let found = bucket
+                .iter()
+                .copied()
+                .find(|&c| same_contents(classes[c].0, p).unwrap_or(false));
+            let class = found.unwrap_or_else(|| {
+                classes.push((p, 0));
+                bucket.push(classes.len() - 1);
+                classes.len() - 1
+            });
+            classes[class].1 += 1;
+            Some(class)
+        })
+        .collect();
+
+    let mut groups = HashMap::new();
+    members
+        .iter()
+        .map(|class| {
+            let class = (*class)?;
+            if classes[class].1 < 2 {
+                return None;
+            }
+            let next = groups.len() + 1;
+            Some(*groups.entry(class).or_insert(next))
+        })
+        .collect()
+}
+
+/// 数字だけからなる値を、列の中で最も長い値の幅まで 0 で埋める
+fn pad_with_zeros(column: Vec<String>) -> Vec<String> {
+    let width = column.iter().map(|v| v.len()).max().unwrap_or(0);
+    column
+        .into_iter()
+        .map(|v| {
+            if v.bytes().all(|b| b.is_ascii_digit()) {
+                format!("{:0>w$}", v, w = width)
+            } else {
+                v
+            }
+        })
+        .collect()
+}
+
+/// 数値の列の文字列を返す。--thousands-sep の場合は 3 桁ごとに区切り、
+/// --pad-with-zeros の場合は 0 で幅をそろえる
+fn numeric_column(values: impl Iterator<Item = u64>, args: &Args) -> Vec<String> {
+    let column = values
+        .map(|v| {
+            if args.thousands_sep {
+                group_thousands(v)
+            } else {
+                v.to_string()
+            }
+        })
+        .collect();
+    if args.pad_with_zeros {
+        pad_with_zeros(column)
+    } else {
+        column
+    }
+}
+
+/// 経過秒数を "45s"、"3d" のように最も大きい単位の 1 つの値で表す
+fn relative_age(seconds: i64) -> String {
+    const UNITS: [(i64, &str); 5] = [
+        (365 * 24 * 60 * 60, "y"),
+        (24 * 60 * 60, "d"),
+        (60 * 60, "h"),
+        (60, "m"),
+        (1, "s"),
+    ];
+
+    // 未来の時刻は経過していないものとして扱う
+    let seconds = seconds.max(0);
+    let (unit, suffix) = UNITS
+        .iter()
+        .find(|(unit, _)| seconds >= *unit)
+        .unwrap_or(&(1, "s"));
+    format!("{}{}", seconds / unit, suffix)
+}
+
+/// 時刻列の文字列を返す。時刻を取得できなかった場合はエラーを報告して "?" を返す。
+/// 伝統的な形式では半年以内なら "Jan  2 15:04"、それ以外は "Jan  2  2006" の形式にする
+fn format_time(path: &Path, time: MyResult<DateTime<Local>>, style: TimeStyle) -> String {
+    const SIX_MONTHS: i64 = 365 * 24 * 60 * 60 / 2;
+
+    match time {
+        Ok(time) if style == TimeStyle::IsoWeek => time.format("%G-W%V-%u").to_string(),
+        Ok(time) if style == TimeStyle::Traditional => {
+            let age = (Local::now() - time).num_seconds();
+            if (0..SIX_MONTHS).contains(&age) {
+                time.format("%b %e %H:%M").to_string()
+            } else {
+                time.format("%b %e  %Y").to_string()
+            }
+        }
+        Ok(time) => time.format("%H:%M").to_string(),
+        Err(e) => {
+            eprintln!("{}: {}", path.display(), e);
+            "?".to_string()
+        }
+    }
+}
+
+/// ls -l のファイル種別の文字を返す
+fn file_type_char(metadata: &Metadata) -> char {
+    let file_type = metadata.file_type();
+    if file_type.is_dir() {
+        'd'
+    } else if file_type.is_symlink() {
+        'l'
+    } else if file_type.is_fifo() {
+        'p'
+    } else if file_type.is_socket() {
+        's'
+    } else if file_type.is_block_device() {
+        'b'
+    } else if file_type.is_char_device() {
+        'c'
+    } else {
+        '-'
+    }
+}
+
+/// coreutils の ls -l と同じ空白の規則で長い形式を返す。リンク数とサイズは右に、
+/// ユーザー名とグループ名は左に寄せ、各列の間は空白 1 つにする。
+/// coreutils と同じく、引数のファイルの列の幅には arg_dirs (引数のディレクトリ自身) も数える
+fn format_compat(
+    paths: &[PathBuf],
+    depth: Option<usize>,
+    arg_dirs: &[PathBuf],
+    args: &Args,
+    colors: Option<&ColorMap>,
+) -> MyResult<String> {
+    let measured: Vec<_> = paths.iter().chain(arg_dirs).cloned().collect();
+    let metadatas = measured
+        .iter()
+        .map(fs::symlink_metadata)
+        .collect::<io::Result<Vec<_>>>()?;
+    // coreutils と同じく、-h の数値は小数点でそろえず単位を付けたまま右にそろえる
+    let sizes: Vec<_> = size_fields(&measured, &metadatas, args)
+        .into_iter()
+        .map(|(number, unit)| number + unit)
+        .collect();
+    let inodes: Vec<_> = metadatas.iter().map(|m| m.ino().to_string()).collect();
+    let blocks: Vec<_> = metadatas
+        .iter()
+        .map(|m| format_block_bytes(m.blocks() * 512, args))
+        .collect();
+    let users: Vec<_> = metadatas
+        .iter()
+        .map(|m| {
+            get_user_by_uid(m.uid()).map_or(m.uid().to_string(), |u| {
+                u.name().to_string_lossy().to_string()
+            })
+        })
+        .collect();
+    let groups: Vec<_> = metadatas
+        .iter()
+        .map(|m| {
+            get_group_by_gid(m.gid()).map_or(m.gid().to_string(), |g| {
+                g.name().to_string_lossy().to_string()
+            })
+        })
+        .collect();
+
+    let width = |column: &[String]| column.iter().map(|c| c.width()).max().unwrap_or(0);
+    let nlinks: Vec<_> = metadatas.iter().map(|m| m.nlink().to_string()).collect();
+    let (nlink_width, user_width, group_width, size_width) =
+        (width(&nlinks), width(&users), width(&groups), width(&sizes));
+
+    let mut output = String::new();
+    for (i, (path, metadata)) in paths.iter().zip(&metadatas).enumerate() {
+        // -i と -s の列は coreutils と同じく許可の前に右寄せで置く
+        if args.inode {
+            write!(output, "{:>w$} ", inodes[i], w = width(&inodes))?;
+        }
+        if args.size {
+            write!(output, "{:>w$} ", blocks[i], w = width(&blocks))?;
+        }
+        let time = entry_time(metadata, TimeField::Modify);
+        let mut name = depth_prefix(depth) + &render_name(path, args, colors);
+        if metadata.file_type().is_symlink() {
+            if let Ok(target) = fs::read_link(path) {
+                name = format!("{} -> {}", name, target.display());
+            }
+        }
+        writeln!(
+            output,
+            "{}{} {:>nw$} {:<uw$} {:<gw$} {:>sw$} {} {}",
+            file_type_char(metadata),
+            format_mode(metadata.mode()),
+            nlinks[i],
+            users[i],
+            groups[i],
+            sizes[i],
+            format_time(path, time, TimeStyle::Traditional),
+            name,
+            nw = nlink_width,
+            uw = user_width,
+            gw = group_width,
+            sw = size_width
+        )?;
+    }
+
+    Ok(output)
+}
+
+fn format_output(
+    paths: &[PathBuf],
+    depth: Option<usize>,
+    args: &Args,
+    colors: Option<&ColorMap>,
+) -> MyResult<String> {
+    let mut fmt = String::new();
+    if args.inode {
+        fmt.push_str("{:>} ");
+    }
+    if args.size {
+        fmt.push_str("{:>} ");
+    }
+    if args.device {
+        fmt.push_str("{:>} ");
+    }
+    if args.fstype {
+        fmt.push_str("{:<} ");
+    }
+    let mut times = if args.all_times {
+        vec![TimeField::Access, TimeField::Modify, TimeField::Change]
+    } else if args.no_time {
+        vec![]
+    } else {
+        vec![TimeField::Modify]
+    };
+    if args.show_birth {
+        times.push(TimeField::Birth);
+    }
+    fmt.push_str("{:<}{:<} {:>} ");
+    if !args.no_user {
+        fmt.push_str("{:<} ");
+    }
+    fmt.push_str("{:<} ");
+    if args.flags {
+        fmt.push_str("{:<} ");
+    }
+    if args.blocks_column {
+        fmt.push_str("{:>} ");
+    }
+    fmt.push_str("{:>} ");
+    if args.relative_size {
+        fmt.push_str("{:>} ");
+    }
+    fmt.push_str(&"{:<} ".repeat(times.len()));
+    if args.age_column {
+        fmt.push_str("{:>} ");
+    }
+    if args.time_deltas {
+        fmt.push_str("{:>} {:>} {:>} ");
+    }
+    fmt.push_str("{:<}");
+    let mut table = Table::new(&fmt);
+    let now = Local::now();
+
+    let metadatas = paths
+        .iter()
+        .map(|p| entry_metadata(p))
+        .collect::<io::Result<Vec<_>>>()?;
+    let inodes = numeric_column(metadatas.iter().map(|m| m.ino()), args);
+    let blocks = numeric_column(
+        metadatas
+            .iter()
+            .map(|m| count_blocks(m, args.blocks_unit())),
+        args,
+    );
+    let max_size = metadatas.iter().map(|m| m.size()).max().unwrap_or(0);
+    let hardlink_groups = if args.show_hardlinks {
+        hardlink_groups(paths)
+    } else {
+        vec![None; metadatas.len()]
+    };
+    let duplicate_groups = if args.find_duplicates {
+        duplicate_groups(paths)
+    } else {
+        vec![None; metadatas.len()]
+    };
+    let mut sizes = format_sizes(paths, &metadatas, args);
+    if args.pad_with_zeros {
+        sizes = pad_with_zeros(sizes);
+    }
+    let relative_sizes = relative_sizes(paths, &metadatas, args);
+
+    for (i, (path, metadata)) in paths.iter().zip(&metadatas).enumerate() {
+        let size = &sizes[i];
+        // --raw-mode では種別と許可の両方を含む st_mode を stat(1) と同じ 8 進数で表す
+        let (file_type, mut mode) = if args.raw_mode {
+            ("", format!("{:06o}", metadata.mode()))
+        } else {
+            (
+                if path.is_dir() { "d" } else { "-" },
+                format_mode(metadata.mode()),
+            )
+        };
+        if args.attrs {
+            mode.push_str(&attr_indicator(file_flags(path)));
+        }
+        let nlink = metadata.nlink();
+
+        let uid = metadata.uid();
+        let user_name = get_user_by_uid(uid)
+            .map(|u| u.name().to_string_lossy().to_string())
+            .unwrap_or_else(|| uid.to_string());
+
+        let gid = metadata.gid();
+        let group_name = get_group_by_gid(gid)
+            .map(|g| g.name().to_string_lossy().to_string())
+            .unwrap_or_else(|| gid.to_string());
+
+        let mut path_name = depth_prefix(depth) + &render_long_name(path, args, colors);
+        if args.only_symlinks {
+            let status = if path.exists() { "ok" } else { "broken" };
+            path_name = format!("{} [{}]", path_name, status);
+        }
+        if args.resolve_chain && path.is_symlink() {
+            path_name.push_str(&symlink_chain(path));
+        }
+        if let Some(group) = hardlink_groups[i] {
+            path_name = format!("{} [hardlink group {}]", path_name, group);
+        }
+        if let Some(group) = duplicate_groups[i] {
+            path_name = format!("{} [dup:{}]", path_name, group);
+        }
 
-    for path in paths {
-        match fs::metadata(path) {
-            Err(e) => {
-                eprintln!("{}: {}", path, e);
-            }
-            Ok(m) => {
-                if m.is_file() {
-                    results.push(PathBuf::from(path));
-                } else {
-                    for entry in fs::read_dir(path)? {
-                        let entry = entry?;
+        let mut row = Row::new();
+        if args.inode {
+            row.add_cell(&inodes[i]); // inode number
+        }
+        if args.size {
+            row.add_cell(&blocks[i]); // allocated blocks
+        }
+        if args.device {
+            row.add_cell(metadata.dev()); // device of the containing file system
+        }
+        if args.fstype {
+            row.add_cell(fstype::fs_type(metadata.dev())); // type of the containing file system
+        }
 
-                        if show_hidden || !entry.file_name().to_string_lossy().starts_with('.') {
-                            results.push(entry.path());
-                        }
-                    }
-                }
+        row.add_cell(file_type) // file type (d or -)
+            .add_cell(mode) // permissions
+            .add_cell(nlink); // link count
+        if !args.no_user {
+            row.add_cell(user_name); // user name
+        }
+        row.add_cell(group_name); // group name
+        if args.flags {
+            row.add_cell(format_flags(file_flags(path))); // file flags
+        }
+        if args.blocks_column {
+            row.add_cell(&blocks[i]); // allocated blocks
+        }
+        match colors {
+            Some(_) if args.color_scale => {
+                let fraction = metadata.size() as f64 / max_size.max(1) as f64;
+                let code = gradient_code(fraction, truecolor());
+                // file size
+                row.add_custom_width_cell(paint(Some(&code), size), size.width());
+            }
+            Some(_) if !args.size_scale_breaks.is_empty() => {
+                let code = size_scale_code(metadata.size(), &args.size_scale_breaks);
+                // file size
+                row.add_custom_width_cell(paint(Some(code), size), size.width());
+            }
+            _ => {
+                row.add_cell(size); // file size
+            }
+        }
+        if args.relative_size {
+            row.add_cell(&relative_sizes[i]); // share of the total size
+        }
+        for &field in &times {
+            let time = match entry_time(metadata, field) {
+                // 作成時刻を記録しないファイルシステムやプラットフォームではエラーにしない
+                Err(_) if field == TimeField::Birth => "-".to_string(),
+                time => format_time(path, time, args.time_style()),
+            };
+            row.add_cell(time); // accessed/updated/changed/created at
+        }
+        if args.age_column {
+            let age = entry_time(metadata, TimeField::Modify)
+                .map_or("?".to_string(), |t| relative_age((now - t).num_seconds()));
+            row.add_cell(age); // time since modified
+        }
+        if args.time_deltas {
+            for field in [TimeField::Access, TimeField::Modify, TimeField::Change] {
+                let delta = entry_time(metadata, field)
+                    .map_or("?".to_string(), |t| relative_age((now - t).num_seconds()));
+                row.add_cell(delta); // time since accessed/modified/changed
             }
         }
+
+        table.add_row(row.with_cell(path_name)); // path
     }
 
-    Ok(results)
+    Ok(format!("{}", table))
 }
 
-pub fn mk_triple(mode: u32, owner: Owner) -> String {
-    let [read, write, execute] = owner.masks();
-    format!(
-        "{}{}{}",
-        if mode & read == 0 { '-' } else { 'r' },
-        if mode & write == 0 { '-' } else { 'w' },
-        if mode & execute == 0 { '-' } else { 'x' }
-    )
+/// --tree の出力と、表示したディレクトリとファイルの数
+#[derive(Default)]
+struct TreeOutput {
+    text: String,
+    dirs: usize,
+    files: usize,
 }
 
-/// 0o751 のような 8 進数でファイルモードを指定すると、
-/// "rwrr-x--x" のような文字列を返す
-fn format_mode(mode: u32) -> String {
-    format!(
-        "{}{}{}",
-        mk_triple(mode, Owner::User),
-        mk_triple(mode, Owner::Group),
-        mk_triple(mode, Owner::Other)
-    )
+/// dir の中を木の形で output に書き込む。depth は dir の深さで、--max-depth より深くは辿らない
+fn write_tree(
+    output: &mut TreeOutput,
+    dir: &Path,
+    prefix: &str,
+    depth: usize,
+    args: &Args,
+    sort: &dyn Fn(&mut [PathBuf]),
+    colors: Option<&ColorMap>,
+) -> MyResult<()> {
+    let [branch, last_branch, pipe, blank] = args.tree_style.connectors();
+    // 読めないディレクトリはエラーを報告して、残りの木を続ける
+    let entries = match read_entries(dir, args.show_hidden, sort) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("{}: {}", dir.display(), e);
+            return Ok(());
+        }
+    };
+
+    for (i, path) in entries.iter().enumerate() {
+        let is_last = i == entries.len() - 1;
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let name = format!("{}{}", name, args.indicator(path));
+        let name = match colors {
+            Some(map) => colorize(path, &name, map),
+            None => name.to_string(),
+        };
+        writeln!(
+            output.text,
+            "{}{}{}",
+            prefix,
+            if is_last { last_branch } else { branch },
+            name
+        )?;
+
+        if path.symlink_metadata().is_ok_and(|m| m.is_dir()) {
+            output.dirs += 1;
+            if args.max_depth.is_none_or(|max| depth + 1 < max) {
+                let prefix = format!("{}{}", prefix, if is_last { blank } else { pipe });
+                write_tree(output, path, &prefix, depth + 1, args, sort, colors)?;
+            }
+        } else {
+            output.files += 1;
+        }
+    }
+
+    Ok(())
 }
 
-fn format_output(paths: &[PathBuf]) -> MyResult<String> {
-    let fmt = "{:<}{:<} {:>} {:<} {:<} {:>} {:<} {:<}";
-    let mut table = Table::new(fmt);
+/// 1 つの区切りのエントリを args で指定された形式で表示する文字列を返す。
+/// --group-by-perms の場合は許可の文字列で並べ替え、同じ許可のまとまりごとに見出しを付ける
+fn format_section(
+    paths: &[PathBuf],
+    depth: Option<usize>,
+    arg_dirs: &[PathBuf],
+    args: &Args,
+    colors: Option<&ColorMap>,
+) -> MyResult<String> {
+    if !args.group_by_perms {
+        return format_collapsed(paths, depth, arg_dirs, args, colors);
+    }
 
-    for path in paths {
-        let metadata = path.metadata()?;
+    let mut entries: Vec<_> = paths
+        .iter()
+        .map(|p| {
+            let perms = entry_metadata(p).map_or("?".to_string(), |m| format_mode(m.mode()));
+            (perms, p.clone())
+        })
+        .collect();
+    // 同じ許可の中では元の並びを保つ
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
 
-        let file_type = if path.is_dir() { "d" } else { "-" };
-        let mode: String = format_mode(metadata.mode());
-        let nlink = metadata.nlink();
+    let mut output = String::new();
+    for group in entries.chunk_by(|a, b| a.0 == b.0) {
+        let paths: Vec<_> = group.iter().map(|(_, p)| p.clone()).collect();
+        writeln!(output, "{}:", group[0].0)?;
+        write!(
+            output,
+            "{}",
+            format_collapsed(&paths, depth, arg_dirs, args, colors)?
+        )?;
+    }
 
-        let uid = metadata.uid();
-        let user_name = get_user_by_uid(uid)
-            .map(|u| u.name().to_string_lossy().to_string())
-            .unwrap_or_else(|| uid.to_string());
+    Ok(output)
+}
 
-        let gid = metadata.gid();
-        let group_name = get_group_by_gid(gid)
-            .map(|g| g.name().to_string_lossy().to_string())
-            .unwrap_or_else(|| gid.to_string());
+/// --collapse-below より小さい通常のファイルを最後の 1 行にまとめて表示する
+fn format_collapsed(
+    paths: &[PathBuf],
+    depth: Option<usize>,
+    arg_dirs: &[PathBuf],
+    args: &Args,
+    colors: Option<&ColorMap>,
+) -> MyResult<String> {
+    let Some(limit) = args.collapse_below else {
+        return format_entries(paths, depth, arg_dirs, args, colors);
+    };
+
+    let (small, shown): (Vec<_>, Vec<_>) = paths
+        .iter()
+        .cloned()
+        .partition(|p| fs::symlink_metadata(p).is_ok_and(|m| m.is_file() && m.size() < limit));
+    let mut output = format_entries(&shown, depth, arg_dirs, args, colors)?;
+    if !small.is_empty() {
+        let total: u64 = small
+            .iter()
+            .filter_map(|p| fs::symlink_metadata(p).ok())
+            .map(|m| m.size())
+            .sum();
+        let line = format!("({} files < {}, total {})", small.len(), limit, total);
+        writeln!(output, "{}", summary(&line, colors))?;
+    }
+
+    Ok(output)
+}
+
+fn format_entries(
+    paths: &[PathBuf],
+    depth: Option<usize>,
+    arg_dirs: &[PathBuf],
+    args: &Args,
+    colors: Option<&ColorMap>,
+) -> MyResult<String> {
+    let format = args.format;
+    if format == OutputFormat::Long && args.compat {
+        return format_compat(paths, depth, arg_dirs, args, colors);
+    }
+    if format == OutputFormat::Long {
+        return format_output(paths, depth, args, colors);
+    }
 
-        let size = metadata.size();
-        let modified = DateTime::<Local>::from(metadata.modified()?).format("%H:%M");
-        let path_name = path.display();
-
-        table.add_row(
-            Row::new()
-                .with_cell(file_type) // file type (d or -)
-                .with_cell(mode) // permissions
-                .with_cell(nlink) // link count
-                .with_cell(user_name) // user name
-                .with_cell(group_name) // group name
-                .with_cell(size) // file size
-                .with_cell(modified) // updated at
-                .with_cell(path_name), // path
+    let lines: Vec<String> = if args.inode || args.size {
+        let fmt = "{:>} ".repeat(args.inode as usize + args.size as usize) + "{:<}";
+        let mut table = Table::new(&fmt);
+        let metadatas = paths
+            .iter()
+            .map(|p| entry_metadata(p))
+            .collect::<io::Result<Vec<_>>>()?;
+        let inodes = numeric_column(metadatas.iter().map(|m| m.ino()), args);
+        let blocks = numeric_column(
+            metadatas
+                .iter()
+                .map(|m| count_blocks(m, args.blocks_unit())),
+            args,
         );
+        for (i, path) in paths.iter().enumerate() {
+            let mut row = Row::new();
+            if args.inode {
+                row.add_cell(&inodes[i]);
+            }
+            if args.size {
+                row.add_cell(&blocks[i]);
+            }
+            table.add_row(row.with_cell(depth_prefix(depth) + &render_name(path, args, colors)));
+        }
+        table.to_string().lines().map(|l| l.to_string()).collect()
+    } else {
+        paths
+            .iter()
+            .map(|p| depth_prefix(depth) + &render_name(p, args, colors))
+            .collect()
+    };
+
+    let output = match format {
+        OutputFormat::Across => grid::across(&lines, args.line_width()),
+        _ => lines.iter().map(|l| format!("{}\n", l)).collect(),
+    };
+
+    Ok(output)
+}
+
+/// 区切りの末尾に付ける "N files, M dirs, T bytes" の行を返す。バイト数はディレクトリ以外の合計
+fn section_summary(entries: &[PathBuf]) -> String {
+    let (mut files, mut dirs, mut bytes) = (0, 0, 0);
+    for metadata in entries.iter().filter_map(|p| entry_metadata(p).ok()) {
+        if metadata.is_dir() {
+            dirs += 1;
+        } else {
+            files += 1;
+            bytes += metadata.size();
+        }
     }
 
-    Ok(format!("{}", table))
+    format!("{} files, {} dirs, {} bytes", files, dirs, bytes)
+}
+
+/// --split-output でディレクトリ dir の一覧を書き込むファイルの名前。
+/// "%" と "/" をそれぞれ "%25" と "%2F" に置き換えて、1 つのファイル名にする。
+/// "." や ".." にならないよう、先頭の "." も "%2E" にする
+fn split_file_name(dir: &Path) -> String {
+    let name = dir
+        .to_string_lossy()
+        .replace('%', "%25")
+        .replace('/', "%2F");
+    match name.strip_prefix('.') {
+        Some(rest) => format!("%2E{}", rest),
+        None => name,
+    }
+}
+
+/// ディレクトリのパスをキーとし、そのエントリの配列を値とする JSON を返す。
+/// 引数で直接与えられたファイルは空文字列のキーにまとめる
+fn format_json_flat(sections: &[Section]) -> MyResult<String> {
+    let mut members = vec![];
+    for section in sections {
+        let key = section
+            .dir
+            .as_ref()
+            .map(|d| d.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let entries = section
+            .entries
+            .iter()
+            .map(|p| Ok(json::entry(p, &entry_metadata(p)?)))
+            .collect::<io::Result<Vec<_>>>()?;
+        members.push(format!("{}:[{}]", json::quote(&key), entries.join(",")));
+    }
+
+    Ok(format!("{{{}}}\n", members.join(",")))
+}
+
+/// 区切りごとに、ディレクトリを表す行に続けてそのエントリを 1 行ずつ JSON で返す。
+/// 引数で直接与えられたファイルはディレクトリの行を付けずに先頭に並べる
+fn format_jsonl(sections: &[Section]) -> MyResult<String> {
+    let mut output = String::new();
+    for section in sections {
+        if let Some(dir) = &section.dir {
+            writeln!(output, "{}", json::directory(dir, section.entries.len()))?;
+        }
+        for path in &section.entries {
+            writeln!(output, "{}", json::entry(path, &entry_metadata(path)?))?;
+        }
+    }
+
+    Ok(output)
+}
+
+/// 再帰の途中でディレクトリのループを見つけて飛ばした場合の終了コード。
+/// エラーの 1 と clap の使い方の誤りの 2 とは区別する
+pub const EXIT_CYCLE: i32 = 3;
+
+/// 解析済みの args に従って一覧を作り、出力する文字列と終了コードを返す
+pub fn run_with_args(args: Args) -> MyResult<(String, i32)> {
+    let mut cycles = false;
+    let output = list(args, boot::boot_time, &mut cycles)?;
+    Ok((output, if cycles { EXIT_CYCLE } else { 0 }))
+}
+
+/// options の形式で paths を一覧にした、run が表示するものと同じ文字列と終了コードを返す。
+/// options に含まれるパスの代わりに paths を使う
+pub fn render(paths: &[String], options: &Args) -> MyResult<(String, i32)> {
+    run_with_args(Args {
+        paths: paths.to_vec(),
+        ..options.clone()
+    })
 }
 
-pub fn run() -> MyResult<()> {
-    let args = Args::parse();
-    let paths = find_files(&args.paths, args.show_hidden)?;
+/// args に従った一覧を返す。--since-boot の基準には boot_time が返す時刻を使う。
+/// 再帰の途中でループを飛ばした場合は cycles を true にする
+fn list(
+    args: Args,
+    boot_time: impl FnOnce() -> Result<SystemTime, String>,
+    cycles: &mut bool,
+) -> MyResult<String> {
+    match args.help {
+        Some(HelpFormat::Text) => return Ok(Args::command().render_help().to_string()),
+        Some(HelpFormat::Json) => return Ok(help_json(&mut Args::command())),
+        None => {}
+    }
+    if let Some(shell) = args.generate_completions {
+        return Ok(completions(&mut Args::command(), shell));
+    }
 
-    if args.long {
-        println!("{}", format_output(&paths)?);
+    let roots = match &args.files0_from {
+        Some(filename) => read_files0(filename)?,
+        None => args.paths.clone(),
+    };
+    let seed = args.seed.unwrap_or_else(random_seed);
+    let sort = |paths: &mut [PathBuf]| {
+        sort_paths(paths, args.sort_key(), args.ignore_case, seed);
+        if args.group_directories_first || args.files_first {
+            group_dirs(paths, args.group_directories_first);
+        }
+    };
+    let colors = if args.color.enabled() {
+        let mut map = match &args.theme {
+            Some(file) => fs::read_to_string(file)
+                .map_err(|e| e.to_string())
+                .and_then(|theme| ColorMap::from_theme(&theme))
+                .map_err(|e| format!("{}: {}", file, e))?,
+            None => ColorMap::from_env(),
+        };
+        map.detect_scripts = args.detect_scripts;
+        Some(map)
     } else {
-        for path in paths {
-            println!("{}", path.display());
+        None
+    };
+
+    if args.color_legend {
+        return Ok(legend(colors.as_ref()));
+    }
+
+    if args.tree {
+        let mut output = TreeOutput::default();
+        for root in &roots {
+            // 存在しない根は find_sections と同じく報告して飛ばす
+            let metadata = match fs::metadata(root) {
+                Ok(m) => m,
+                Err(e) => {
+                    eprintln!("{}: {}", root, e);
+                    continue;
+                }
+            };
+            let name = render_name(Path::new(root), &args, colors.as_ref());
+            writeln!(output.text, "{}", name)?;
+            if metadata.is_dir() && args.max_depth != Some(0) {
+                write_tree(
+                    &mut output,
+                    Path::new(root),
+                    "",
+                    0,
+                    &args,
+                    &sort,
+                    colors.as_ref(),
+                )?;
+            }
         }
+        if args.max_depth.is_some() {
+            let line = format!("{} directories, {} files", output.dirs, output.files);
+            writeln!(output.text, "\n{}", summary(&line, colors.as_ref()))?;
+        }
+        return Ok(output.text);
     }
 
-    Ok(())
+    let since = args.since_boot.then(boot_time).transpose()?;
+    let uid = match args.uid {
+        Some(uid) => Some(uid),
+        None => args.owner.as_deref().map(resolve_uid).transpose()?,
+    };
+    let gid = match args.gid {
+        Some(gid) => Some(gid),
+        None => args.group.as_deref().map(resolve_gid).transpose()?,
+    };
+    // 基準のファイルより更新時刻が (新しい, 古い) ものを (残す, 除く) 条件
+    let mut windows = vec![];
+    for (file, order, keep) in [
+        (&args.newer, Ordering::Greater, true),
+        (&args.older, Ordering::Less, true),
+        (&args.exclude_newer, Ordering::Greater, false),
+        (&args.exclude_older, Ordering::Less, false),
+    ] {
+        if let Some(file) = file {
+            windows.push((reference_time(file)?, order, keep));
+        }
+    }
+    let filter = |p: &Path| {
+        if args.only_symlinks && !p.is_symlink() {
+            return false;
+        }
+        let executables = args.executable || args.executable_files;
+        if since.is_some() || uid.is_some() || gid.is_some() || executables || !windows.is_empty() {
+            let Ok(m) = entry_metadata(p) else {
+                return false;
+            };
+            if (executables && !is_executable(m.mode()))
+                || (args.executable_files && m.is_dir())
+                || since.is_some_and(|t| !modified_since(&m, t))
+                || uid.is_some_and(|u| m.uid() != u)
+                || gid.is_some_and(|g| m.gid() != g)
+                || !windows.iter().all(|&(time, order, keep)| {
+                    m.modified().is_ok_and(|t| (t.cmp(&time) == order) == keep)
+                })
+            {
+                return false;
+            }
+        }
+        true
+    };
+
+    let mut walk = Walk::new(args.show_hidden, args.recursive, &sort);
+    walk.exclude = &args.exclude;
+    walk.filter = &filter;
+    walk.warn_large = args.warn_large;
+    walk.max_entries = args.max_entries;
+    walk.remaining = args.max_entries_total;
+    walk.immediate = args.depth_first_immediate;
+    walk.follow_symlinks = args.follow_symlinks;
+    walk.follow_args = args.follow_args_only;
+    walk.min_depth = args.min_depth;
+    walk.prune_empty = args.prune_empty;
+    let sections = walk.find_sections(&roots)?;
+    *cycles = walk.cycles;
+    if walk.truncated {
+        eprintln!(
+            "lsr: listing truncated after {} entries",
+            args.max_entries_total.unwrap_or_default()
+        );
+    }
+
+    if args.json_flat {
+        return format_json_flat(&sections);
+    }
+    if args.jsonl {
+        return format_jsonl(&sections);
+    }
+
+    if let Some(out_dir) = &args.split_output {
+        fs::create_dir_all(out_dir)?;
+        for section in &sections {
+            let name = section
+                .dir
+                .as_ref()
+                .map_or("-".to_string(), |d| split_file_name(d));
+            let listing = total_line(section, &args, colors.as_ref())
+                + &format_section(&section.entries, None, &[], &args, colors.as_ref())?;
+            fs::write(Path::new(out_dir).join(name), listing)?;
+        }
+        return Ok(String::new());
+    }
+
+    // 複数の引数を与えた場合と再帰する場合はディレクトリごとに見出しを付ける
+    let show_headers = args.recursive || roots.len() > 1;
+    let mut output = String::new();
+    for (i, section) in sections.iter().enumerate() {
+        if i > 0 && !args.no_group_blank_lines {
+            writeln!(output)?;
+        }
+        let depth = args.show_depth.then_some(section.depth);
+        if let (true, Some(dir)) = (show_headers, &section.dir) {
+            writeln!(output, "{}:", dir.display())?;
+        }
+        output.push_str(&total_line(section, &args, colors.as_ref()));
+        let arg_dirs = match section.dir {
+            Some(_) => &[][..],
+            None => &walk.arg_dirs,
+        };
+        write!(
+            output,
+            "{}",
+            format_section(&section.entries, depth, arg_dirs, &args, colors.as_ref())?
+        )?;
+        if args.summary_per_dir {
+            let line = section_summary(&section.entries);
+            writeln!(output, "{}", summary(&line, colors.as_ref()))?;
+        }
+    }
+    if colors.is_some() {
+        ensure_reset(&mut output);
+    }
+
+    Ok(output)
+}
+
+/// 一覧を表示して終了コードを返す
+pub fn run() -> MyResult<i32> {
+    let (output, code) = run_with_args(Args::parse())?;
+    print!("{}", output);
+
+    Ok(code)
 }
 
 #[cfg(test)]
 mod tests {
-    use std::path::PathBuf;
+    use std::{
+        fs,
+        path::PathBuf,
+        time::{Duration, SystemTime},
+    };
 
-    use crate::{format_output, mk_triple, owner::Owner};
+    use chrono::TimeZone;
+    use clap::Parser;
 
-    use super::{find_files, format_mode};
+    use crate::{
+        fit_width, format_output, format_time, group_thousands, human_size, list, matches_pattern,
+        mk_triple, modified_since, owner::Owner, pad_with_zeros, parse_size, relative_age, render,
+        run_with_args, shuffle, Args, TimeStyle, UnitStyle, EXIT_CYCLE,
+    };
+
+    use super::{discover, find_files, format_mode, same_contents, DiscoverOptions};
 
     fn long_match(
         line: &str,
@@ -162,7 +2590,7 @@ mod tests {
     #[test]
     fn test_find_files() {
         // ディレクトリにある隠しエントリ以外のエントリを返す
-        let res = find_files(&["tests/inputs".to_string()], false);
+        let res = find_files(&["tests/inputs".to_string()], false, false);
         assert!(res.is_ok());
         let mut filenames: Vec<_> = res
             .unwrap()
@@ -181,7 +2609,7 @@ mod tests {
         );
 
         // ファイルを直接指定した場合は、隠しファイルであっても返す
-        let res = find_files(&["tests/inputs/.hidden".to_string()], false);
+        let res = find_files(&["tests/inputs/.hidden".to_string()], false, false);
         assert!(res.is_ok());
         let filenames: Vec<_> = res
             .unwrap()
@@ -197,6 +2625,7 @@ mod tests {
                 "tests/inputs/dir".to_string(),
             ],
             false,
+            false,
         );
         assert!(res.is_ok());
         let mut filenames: Vec<_> = res
@@ -211,9 +2640,63 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_discover() {
+        // ディレクトリの中身は read_dir の順のまま返す
+        let options = DiscoverOptions {
+            show_hidden: true,
+            recursive: false,
+        };
+        let entries = discover(&["tests/inputs".to_string()], options).unwrap();
+        let raw: Vec<_> = fs::read_dir("tests/inputs")
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .collect();
+        assert_eq!(
+            entries.iter().map(|e| &e.path).collect::<Vec<_>>(),
+            raw.iter().collect::<Vec<_>>()
+        );
+        assert!(entries
+            .iter()
+            .all(|e| e.dir == Some(PathBuf::from("tests/inputs"))));
+
+        // ファイルの引数は引数の順のまま、見出しのない区切りに入る
+        let paths = ["tests/inputs/fox.txt", "tests/inputs/bustle.txt"].map(String::from);
+        let entries = discover(&paths, DiscoverOptions::default()).unwrap();
+        assert_eq!(
+            entries,
+            paths.map(|p| super::Entry {
+                path: PathBuf::from(p),
+                dir: None
+            })
+        );
+    }
+
     #[test]
     fn test_find_files_hidden() {
-        let res = find_files(&["tests/inputs".to_string()], true);
+        let res = find_files(&["tests/inputs".to_string()], true, false);
+        assert!(res.is_ok());
+        let mut filenames: Vec<_> = res
+            .unwrap()
+            .iter()
+            .map(|e| e.display().to_string())
+            .collect();
+        filenames.sort();
+        assert_eq!(
+            filenames,
+            [
+                "tests/inputs/.hidden",
+                "tests/inputs/bustle.txt",
+                "tests/inputs/dir",
+                "tests/inputs/empty.txt",
+                "tests/inputs/fox.txt"
+            ]
+        )
+    }
+
+    #[test]
+    fn test_find_files_recursive() {
+        let res = find_files(&["tests/inputs".to_string()], true, true);
         assert!(res.is_ok());
         let mut filenames: Vec<_> = res
             .unwrap()
@@ -227,12 +2710,72 @@ mod tests {
                 "tests/inputs/.hidden",
                 "tests/inputs/bustle.txt",
                 "tests/inputs/dir",
+                "tests/inputs/dir/.gitkeep",
+                "tests/inputs/dir/spiders.txt",
                 "tests/inputs/empty.txt",
                 "tests/inputs/fox.txt"
             ]
         )
     }
 
+    #[test]
+    fn test_modified_since() {
+        let metadata = fs::metadata("tests/inputs/bustle.txt").unwrap();
+
+        // 起動時刻が更新時刻より前なら残す
+        assert!(modified_since(&metadata, SystemTime::UNIX_EPOCH));
+
+        // 起動時刻が未来なら除く
+        let future = SystemTime::now() + Duration::from_secs(3600);
+        assert!(!modified_since(&metadata, future));
+    }
+
+    #[test]
+    fn test_same_contents() {
+        let dir = std::env::temp_dir().join(format!("lsr-same-contents-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // 読み込みの区切りをまたぐ大きさにして、最後のバイトだけを変える
+        let contents = vec![b'a'; 10000];
+        let mut changed = contents.clone();
+        changed[9999] = b'b';
+        for (name, data) in [("a", &contents), ("b", &contents), ("c", &changed)] {
+            fs::write(dir.join(name), data).unwrap();
+        }
+        let same = same_contents(&dir.join("a"), &dir.join("b")).unwrap();
+        let differ = same_contents(&dir.join("a"), &dir.join("c")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(same);
+        assert!(!differ);
+    }
+
+    #[test]
+    fn test_since_boot() {
+        let dir = std::env::temp_dir().join(format!("lsr-since-boot-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let old = dir.join("old.txt");
+        let new = dir.join("new.txt");
+        fs::write(&old, "").unwrap();
+        fs::write(&new, "").unwrap();
+        let booted = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        fs::File::options()
+            .write(true)
+            .open(&old)
+            .unwrap()
+            .set_modified(booted - Duration::from_secs(60))
+            .unwrap();
+
+        // 起動時刻より前に更新したファイルを除く
+        let args = Args::parse_from(["lsr", "--since-boot", dir.to_str().unwrap()]);
+        let res = list(args, || Ok(booted), &mut false);
+        let args = Args::parse_from(["lsr", "--since-boot", dir.to_str().unwrap()]);
+        let err = list(args, || Err("no boot time".to_string()), &mut false);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(res.unwrap(), format!("{}\n", new.display()));
+
+        // 起動時刻がわからなければエラーにする
+        assert_eq!(err.unwrap_err().to_string(), "no boot time");
+    }
+
     #[test]
     fn test_mk_triple() {
         assert_eq!(mk_triple(0o751, Owner::User), "rwx");
@@ -247,12 +2790,154 @@ mod tests {
         assert_eq!(format_mode(0o644), "rw-r--r--");
     }
 
+    #[test]
+    fn test_group_thousands() {
+        assert_eq!(group_thousands(0), "0");
+        assert_eq!(group_thousands(999), "999");
+        assert_eq!(group_thousands(1000), "1,000");
+        assert_eq!(group_thousands(1234567), "1,234,567");
+    }
+
+    #[test]
+    fn test_human_size() {
+        assert_eq!(human_size(193), ("193".to_string(), ""));
+        assert_eq!(human_size(1024), ("1.0".to_string(), "K"));
+        assert_eq!(human_size(1228), ("1.2".to_string(), "K"));
+        assert_eq!(human_size(15 * 1024 * 1024), ("15".to_string(), "M"));
+        assert_eq!(human_size(3 * 1024 * 1024 * 1024), ("3.0".to_string(), "G"));
+        // 切り上げで 1024 に達した場合は次の単位にする
+        assert_eq!(human_size(1024 * 1024 - 1), ("1.0".to_string(), "M"));
+    }
+
+    #[test]
+    fn test_format_time() {
+        let path = PathBuf::from("tests/inputs/bustle.txt");
+        assert_eq!(
+            format_time(&path, Err("unsupported".into()), TimeStyle::Default),
+            "?"
+        );
+
+        let time = chrono::Local::now();
+        assert_eq!(
+            format_time(&path, Ok(time), TimeStyle::Default),
+            time.format("%H:%M").to_string()
+        );
+
+        // POSIX の形式では半年より前のものは時刻の代わりに年を表示する
+        let (recent, old) = (
+            time - Duration::from_secs(60),
+            time - Duration::from_secs(1 << 25),
+        );
+        assert_eq!(
+            format_time(&path, Ok(recent), TimeStyle::Traditional),
+            recent.format("%b %e %H:%M").to_string()
+        );
+        assert_eq!(
+            format_time(&path, Ok(old), TimeStyle::Traditional),
+            old.format("%b %e  %Y").to_string()
+        );
+
+        // 2024-01-07 は 2024 年の第 1 週の日曜日
+        let sunday = chrono::Local
+            .with_ymd_and_hms(2024, 1, 7, 12, 0, 0)
+            .unwrap();
+        assert_eq!(
+            format_time(&path, Ok(sunday), TimeStyle::IsoWeek),
+            "2024-W01-7"
+        );
+    }
+
+    #[test]
+    fn test_matches_pattern() {
+        assert!(matches_pattern("*.txt", "bustle.txt"));
+        assert!(matches_pattern("b?stle.*", "bustle.txt"));
+        assert!(matches_pattern("[ab]*", "bustle.txt"));
+        assert!(matches_pattern("[a-c]*", "bustle.txt"));
+        assert!(!matches_pattern("[!b]*", "bustle.txt"));
+        assert!(!matches_pattern("*.txt", "dir"));
+        assert!(!matches_pattern("*.txt", "bustle.txt.bak"));
+    }
+
+    #[test]
+    fn test_pad_with_zeros() {
+        let column = ["7", "12345", "-", "890"].map(String::from).to_vec();
+        assert_eq!(pad_with_zeros(column), ["00007", "12345", "-", "00890"]);
+        assert!(pad_with_zeros(vec![]).is_empty());
+    }
+
+    #[test]
+    fn test_fit_width() {
+        assert_eq!(fit_width("fox.txt", 10), "fox.txt   ");
+        assert_eq!(fit_width("abcdefghijklmnop.txt", 10), "abcdefghi…");
+        // 結合文字は前の文字と一緒に残すか一緒に落とす
+        assert_eq!(fit_width("cafe\u{301}-au-lait", 6), "cafe\u{301}-…");
+        assert_eq!(fit_width("cafe\u{301}-au-lait", 5), "cafe\u{301}…");
+        assert_eq!(fit_width("cafe\u{301}-au-lait", 4), "caf…");
+        // 全角文字が入らない場合は空白で埋める
+        assert_eq!(fit_width("日本語.txt", 6), "日本… ");
+        // 国旗の絵文字は 2 つの地域指示子を分けない
+        let flags = "\u{1f1ef}\u{1f1f5}\u{1f1fa}\u{1f1f8}.txt";
+        assert_eq!(fit_width(flags, 4), "\u{1f1ef}\u{1f1f5}… ");
+    }
+
+    #[test]
+    fn test_relative_age() {
+        assert_eq!(relative_age(0), "0s");
+        assert_eq!(relative_age(59), "59s");
+        assert_eq!(relative_age(60), "1m");
+        assert_eq!(relative_age(2 * 60 * 60 + 59), "2h");
+        assert_eq!(relative_age(3 * 24 * 60 * 60), "3d");
+        assert_eq!(relative_age(400 * 24 * 60 * 60), "1y");
+        assert_eq!(relative_age(-10), "0s");
+    }
+
+    #[test]
+    fn test_shuffle() {
+        let paths: Vec<_> = (0..20).map(|i| PathBuf::from(i.to_string())).collect();
+
+        // 同じ種からは同じ並びになり、要素は失われない
+        let mut a = paths.clone();
+        let mut b = paths.clone();
+        shuffle(&mut a, 42);
+        shuffle(&mut b, 42);
+        assert_eq!(a, b);
+        assert_ne!(a, paths);
+        a.sort();
+        let mut sorted = paths.clone();
+        sorted.sort();
+        assert_eq!(a, sorted);
+
+        let mut c = paths.clone();
+        shuffle(&mut c, 43);
+        assert_ne!(b, c);
+    }
+
+    #[test]
+    fn test_unit_style_label() {
+        assert_eq!(UnitStyle::Short.label("M"), "M");
+        assert_eq!(UnitStyle::Binary.label("K"), "KiB");
+        assert_eq!(UnitStyle::Decimal.label("E"), "EB");
+        assert_eq!(UnitStyle::Binary.label(""), "");
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("0"), Ok(0));
+        assert_eq!(parse_size("193"), Ok(193));
+        assert_eq!(parse_size("1K"), Ok(1024));
+        assert_eq!(parse_size("15m"), Ok(15 * 1024 * 1024));
+        assert_eq!(parse_size("3G"), Ok(3 * 1024 * 1024 * 1024));
+        assert!(parse_size("").is_err());
+        assert!(parse_size("1X").is_err());
+        assert!(parse_size("99999999E").is_err());
+    }
+
     #[test]
     fn test_format_output_one() {
         let bustle_path = "tests/inputs/bustle.txt";
         let bustle = PathBuf::from(bustle_path);
 
-        let res = format_output(&[bustle]);
+        let res = format_output(&[bustle], None, &Args::parse_from(["lsr"]), None);
         assert!(res.is_ok());
 
         let out = res.unwrap();
@@ -265,10 +2950,15 @@ mod tests {
 
     #[test]
     fn test_format_output_two() {
-        let res = format_output(&[
-            PathBuf::from("tests/inputs/dir"),
-            PathBuf::from("tests/inputs/empty.txt"),
-        ]);
+        let res = format_output(
+            &[
+                PathBuf::from("tests/inputs/dir"),
+                PathBuf::from("tests/inputs/empty.txt"),
+            ],
+            None,
+            &Args::parse_from(["lsr"]),
+            None,
+        );
         assert!(res.is_ok());
 
         let out = res.unwrap();
@@ -278,4 +2968,106 @@ mod tests {
         let empty_line = lines.remove(0);
         long_match(empty_line, "tests/inputs/empty.txt", "-rw-r--r--", None);
     }
+
+    #[test]
+    fn test_render_modes() {
+        use std::os::unix::fs::MetadataExt;
+
+        let files = ["bustle.txt", "empty.txt", "fox.txt"].map(|f| format!("tests/inputs/{}", f));
+        let options = |flags: &[&str]| Args::parse_from(["lsr"].iter().chain(flags));
+
+        // 1 行に 1 つ
+        assert_eq!(
+            render(&files, &options(&[])).unwrap(),
+            (
+                "tests/inputs/bustle.txt\ntests/inputs/empty.txt\ntests/inputs/fox.txt\n".into(),
+                0
+            )
+        );
+
+        // 長い形式。時刻の列を除けば出力全体が決まる
+        let metadata: Vec<_> = files.iter().map(|f| fs::metadata(f).unwrap()).collect();
+        let user = users::get_user_by_uid(metadata[0].uid()).unwrap();
+        let group = users::get_group_by_gid(metadata[0].gid()).unwrap();
+        let owner = format!(
+            "{} {}",
+            user.name().to_string_lossy(),
+            group.name().to_string_lossy()
+        );
+        assert_eq!(
+            render(&files, &options(&["-l", "--no-time"])).unwrap().0,
+            format!(
+                "-rw-r--r-- 1 {owner} 193 tests/inputs/bustle.txt\n\
+                 -rw-r--r-- 1 {owner}   0 tests/inputs/empty.txt\n\
+                 -rw-r--r-- 1 {owner}  45 tests/inputs/fox.txt\n"
+            )
+        );
+
+        // JSON Lines
+        let expected: String = files
+            .iter()
+            .zip(&metadata)
+            .map(|(f, m)| {
+                format!(
+                    "{{\"name\":\"{}\",\"path\":\"{}\",\"type\":\"file\",\"mode\":\"rw-r--r--\",\
+                     \"nlink\":1,\"uid\":{},\"gid\":{},\"size\":{},\"modified\":{}}}\n",
+                    &f["tests/inputs/".len()..],
+                    f,
+                    m.uid(),
+                    m.gid(),
+                    m.size(),
+                    m.mtime()
+                )
+            })
+            .collect();
+        assert_eq!(render(&files, &options(&["--jsonl"])).unwrap().0, expected);
+
+        // options に含まれるパスは使わない
+        let options = Args::parse_from(["lsr", "--json-flat", "tests/inputs/dir"]);
+        let (json, _) = render(&files[2..], &options).unwrap();
+        assert!(json.starts_with(r#"{"":[{"name":"fox.txt","path":"tests/inputs/fox.txt","#));
+        assert!(json.ends_with("}]}\n"));
+    }
+
+    #[test]
+    fn test_run_with_args() {
+        // ディレクトリを指定すると名前順にエントリを返す
+        let args = Args::parse_from(["lsr", "tests/inputs"]);
+        let res = run_with_args(args);
+        assert!(res.is_ok());
+        assert_eq!(
+            res.unwrap(),
+            (
+                "tests/inputs/bustle.txt\n\
+                 tests/inputs/dir\n\
+                 tests/inputs/empty.txt\n\
+                 tests/inputs/fox.txt\n"
+                    .into(),
+                0
+            )
+        );
+
+        // ロングフォーマットでも全体の流れを通せる
+        let args = Args::parse_from(["lsr", "-l", "tests/inputs/bustle.txt"]);
+        let res = run_with_args(args);
+        assert!(res.is_ok());
+        let (out, _) = res.unwrap();
+        let lines: Vec<&str> = out.split('\n').filter(|s| !s.is_empty()).collect();
+        assert_eq!(lines.len(), 1);
+        long_match(
+            lines[0],
+            "tests/inputs/bustle.txt",
+            "-rw-r--r--",
+            Some("193"),
+        );
+
+        // 再帰の途中でループを飛ばすと、出力と一緒に EXIT_CYCLE を返す
+        let dir = std::env::temp_dir().join(format!("lsr-run-cycle-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        std::os::unix::fs::symlink("..", dir.join("sub/up")).unwrap();
+        let args = Args::parse_from(["lsr", "-R", "--follow-symlinks", dir.to_str().unwrap()]);
+        let res = run_with_args(args);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(res.unwrap().1, EXIT_CYCLE);
+    }
 }
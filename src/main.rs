@@ -1,6 +1,9 @@
 fn main() {
-    if let Err(e) = lsr::run() {
-        eprint!("{}", e);
-        std::process::exit(1);
+    match lsr::run() {
+        Ok(code) => std::process::exit(code),
+        Err(e) => {
+            eprint!("{}", e);
+            std::process::exit(1);
+        }
     }
 }
@@ -1,7 +1,12 @@
 use assert_cmd::Command;
 use predicates::prelude::*;
 use rand::{distributions::Alphanumeric, Rng};
-use std::{error::Error, fs};
+use std::{
+    error::Error,
+    fs,
+    os::unix::fs::{symlink, MetadataExt, PermissionsExt},
+    path::PathBuf,
+};
 
 type TestResult = Result<(), Box<dyn Error>>;
 
@@ -25,6 +30,21 @@ fn gen_bad_file() -> String {
     }
 }
 
+fn gen_temp_dir() -> PathBuf {
+    loop {
+        let name: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(7)
+            .map(char::from)
+            .collect();
+        let dir = std::env::temp_dir().join(format!("lsr-{}", name));
+
+        if fs::create_dir(&dir).is_ok() {
+            return dir;
+        }
+    }
+}
+
 #[test]
 fn bad_file() -> TestResult {
     let bad = gen_bad_file();
@@ -159,10 +179,21 @@ fn dir2_all() -> TestResult {
     )
 }
 
+/// ディレクトリのロングフォーマットの出力から、先頭の total の行を除いたエントリの行を返す
+fn long_entries(stdout: &str) -> Vec<&str> {
+    let mut lines = stdout.lines();
+    let total = lines.next().unwrap_or_default();
+    assert!(total.starts_with("total "), "{}", stdout);
+    lines.collect()
+}
+
 fn dir_long(args: &[&str], expected: &[(&str, &str, &str)]) -> TestResult {
     let cmd = Command::cargo_bin(PRG)?.args(args).assert().success();
     let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
-    let lines: Vec<&str> = stdout.split('\n').filter(|s| !s.is_empty()).collect();
+    let lines: Vec<&str> = long_entries(&stdout)
+        .into_iter()
+        .filter(|s| !s.is_empty())
+        .collect();
     assert_eq!(lines.len(), expected.len());
 
     let mut check = vec![];
@@ -229,3 +260,2420 @@ fn dir2_long_all() -> TestResult {
         ],
     )
 }
+
+#[test]
+fn color_orphan() -> TestResult {
+    let dir = gen_temp_dir();
+    symlink(dir.join("nowhere"), dir.join("dangling"))?;
+    let expected = format!("\x1b[40;31;01m{}\x1b[0m\n", dir.join("dangling").display());
+    let output = Command::cargo_bin(PRG)?
+        .env_remove("LS_COLORS")
+        .args(["--color=always", dir.to_str().unwrap()])
+        .output()?;
+    fs::remove_dir_all(&dir)?;
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout)?, expected);
+    Ok(())
+}
+
+#[test]
+fn sort_extension_ignore_case() -> TestResult {
+    let dir = gen_temp_dir();
+    for name in ["a.txt", "b.MD", "c.TXT", "d.md"] {
+        fs::write(dir.join(name), "")?;
+    }
+    let run = |args: &[&str]| -> Result<Vec<String>, Box<dyn Error>> {
+        let output = Command::cargo_bin(PRG)?.args(args).arg(&dir).output()?;
+        let stdout = String::from_utf8(output.stdout)?;
+        Ok(stdout
+            .lines()
+            .map(|l| l.rsplit('/').next().unwrap().to_string())
+            .collect())
+    };
+
+    let sensitive = run(&["-X"]);
+    let insensitive = run(&["-X", "--ignore-case"]);
+    fs::remove_dir_all(&dir)?;
+
+    // 大文字・小文字を区別すると、大文字の拡張子が先に並ぶ
+    assert_eq!(sensitive?, ["b.MD", "c.TXT", "d.md", "a.txt"]);
+    // --ignore-case では同じ拡張子がまとまる
+    assert_eq!(insensitive?, ["b.MD", "d.md", "a.txt", "c.TXT"]);
+    Ok(())
+}
+
+#[test]
+fn long_thousands_sep() -> TestResult {
+    let dir = gen_temp_dir();
+    let file = dir.join("large.bin");
+    fs::write(&file, vec![1; 1234567])?;
+    let output = Command::cargo_bin(PRG)?
+        .args(["--long", "--thousands-sep"])
+        .arg(&file)
+        .output()?;
+    let blocks = Command::cargo_bin(PRG)?
+        .args(["--long", "--thousands-sep", "-i", "-s"])
+        .arg(&file)
+        .output()?;
+    let metadata = fs::metadata(&file)?;
+    fs::remove_dir_all(&dir)?;
+
+    let stdout = String::from_utf8(output.stdout)?;
+    let parts: Vec<_> = stdout.split_whitespace().collect();
+    assert_eq!(parts.get(4).unwrap(), &"1,234,567");
+
+    // inode 番号とブロック数も区切る。1234567 バイトは 1 KiB のブロックで 1,000 を超える
+    let stdout = String::from_utf8(blocks.stdout)?;
+    let parts: Vec<_> = stdout.split_whitespace().collect();
+    let grouped = |n: u64| {
+        let digits = n.to_string();
+        let groups: Vec<_> = digits.as_bytes().rchunks(3).rev().collect();
+        String::from_utf8(groups.join(&b',')).unwrap()
+    };
+    assert_eq!(parts[0], grouped(metadata.ino()));
+    assert_eq!(parts[1], grouped(metadata.blocks().div_ceil(2)));
+    assert!(parts[1].contains(','), "{}", stdout);
+    assert_eq!(parts.get(6).unwrap(), &"1,234,567");
+    Ok(())
+}
+
+#[test]
+fn only_symlinks_long() -> TestResult {
+    let dir = gen_temp_dir();
+    fs::write(dir.join("target.txt"), "")?;
+    symlink(dir.join("target.txt"), dir.join("valid"))?;
+    symlink(dir.join("nowhere"), dir.join("broken"))?;
+    let output = Command::cargo_bin(PRG)?
+        .args(["--long", "--only-symlinks"])
+        .arg(&dir)
+        .output()?;
+    fs::remove_dir_all(&dir)?;
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    let lines = long_entries(&stdout);
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].ends_with(&format!("{} [broken]", dir.join("broken").display())));
+    assert!(lines[1].ends_with(&format!("{} [ok]", dir.join("valid").display())));
+    Ok(())
+}
+
+#[test]
+fn recursive_files0_from() -> TestResult {
+    let dir = gen_temp_dir();
+    let list = dir.join("roots");
+    fs::write(&list, "tests/inputs\0")?;
+    let output = Command::cargo_bin(PRG)?
+        .arg("-R")
+        .arg(format!("--files0-from={}", list.display()))
+        .output()?;
+    fs::remove_dir_all(&dir)?;
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.lines().any(|l| l == "tests/inputs/dir/spiders.txt"));
+    Ok(())
+}
+
+#[test]
+fn color_other_writable_dir() -> TestResult {
+    let dir = gen_temp_dir();
+    let shared = dir.join("shared");
+    fs::create_dir(&shared)?;
+    fs::set_permissions(&shared, fs::Permissions::from_mode(0o777))?;
+    let output = Command::cargo_bin(PRG)?
+        .env_remove("LS_COLORS")
+        .arg("--color=always")
+        .arg(&dir)
+        .output()?;
+    fs::remove_dir_all(&dir)?;
+
+    let expected = format!("\x1b[34;42m{}\x1b[0m\n", shared.display());
+    assert_eq!(String::from_utf8(output.stdout)?, expected);
+    Ok(())
+}
+
+#[test]
+fn size_blocks_size() -> TestResult {
+    let dir = gen_temp_dir();
+    let file = dir.join("data.bin");
+    fs::write(&file, vec![b'x'; 5000])?;
+    let blocks = fs::metadata(&file)?.blocks();
+    let output = Command::cargo_bin(PRG)?
+        .args(["-s", "--blocks-size=4096"])
+        .arg(&file)
+        .output()?;
+    fs::remove_dir_all(&dir)?;
+
+    let expected = format!("{} {}\n", (blocks * 512).div_ceil(4096), file.display());
+    assert_eq!(String::from_utf8(output.stdout)?, expected);
+    Ok(())
+}
+
+#[test]
+fn long_all_times() -> TestResult {
+    let cmd = Command::cargo_bin(PRG)?
+        .args(["--long", "--all-times", FOX])
+        .assert()
+        .success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+    let parts: Vec<_> = stdout.split_whitespace().collect();
+    assert_eq!(parts.len(), 9);
+
+    // アクセス・更新・変更時刻の 3 列が HH:MM 形式で並ぶ
+    for time in &parts[5..8] {
+        let (hour, minute) = time.split_once(':').unwrap();
+        assert!(hour.parse::<u32>()? < 24);
+        assert!(minute.parse::<u32>()? < 60);
+    }
+    assert_eq!(parts.last().unwrap(), &FOX);
+    Ok(())
+}
+
+/// ロングフォーマットの行で、時刻の列の直前にあるサイズの列の終端のバイト位置を返す
+fn size_end(line: &str) -> usize {
+    let time = line
+        .split_whitespace()
+        .find(|t| t.len() == 5 && t.as_bytes()[2] == b':')
+        .unwrap();
+    time.as_ptr() as usize - line.as_ptr() as usize - 1
+}
+
+fn long_human_readable(files: &[(&str, u64)]) -> Result<Vec<String>, Box<dyn Error>> {
+    let dir = gen_temp_dir();
+    for (name, len) in files {
+        fs::File::create(dir.join(name))?.set_len(*len)?;
+    }
+    let output = Command::cargo_bin(PRG)?.arg("-lh").arg(&dir).output()?;
+    fs::remove_dir_all(&dir)?;
+
+    let stdout = String::from_utf8(output.stdout)?;
+    Ok(long_entries(&stdout)
+        .into_iter()
+        .filter(|l| !l.is_empty())
+        .map(|l| l.to_string())
+        .collect())
+}
+
+#[test]
+fn long_human_readable_units_align() -> TestResult {
+    let lines = long_human_readable(&[
+        ("a.bin", 1228),
+        ("b.bin", 15 * 1024 * 1024),
+        ("c.bin", 3 * 1024 * 1024 * 1024),
+        ("d.txt", 193),
+    ])?;
+    assert_eq!(lines.len(), 4);
+
+    // 単位はすべて同じ列に並び、単位のない値はその列を空白で埋める
+    let units: Vec<_> = lines.iter().map(|l| &l[..size_end(l)]).collect();
+    assert!(units[0].ends_with(" 1.2K"));
+    assert!(units[1].ends_with(" 15  M"));
+    assert!(units[2].ends_with(" 3.0G"));
+    assert!(units[3].ends_with(" 193   "));
+    assert!(units.windows(2).all(|w| w[0].len() == w[1].len()));
+    Ok(())
+}
+
+#[test]
+fn long_human_readable_unit_style() -> TestResult {
+    let dir = gen_temp_dir();
+    let file = dir.join("a.bin");
+    fs::File::create(&file)?.set_len(1228)?;
+    let size = |style: &str| -> Result<String, Box<dyn Error>> {
+        let output = Command::cargo_bin(PRG)?
+            .args(["-lh", &format!("--unit-style={}", style)])
+            .arg(&file)
+            .output()?;
+        let stdout = String::from_utf8(output.stdout)?;
+        Ok(stdout.split_whitespace().nth(4).unwrap().to_string())
+    };
+    let short = size("short")?;
+    let binary = size("binary")?;
+    let decimal = size("decimal")?;
+    fs::remove_dir_all(&dir)?;
+
+    // 割る数は変わらず、単位の表記だけが変わる
+    assert_eq!(short, "1.2K");
+    assert_eq!(binary, "1.2KiB");
+    assert_eq!(decimal, "1.2KB");
+    Ok(())
+}
+
+#[test]
+fn long_human_readable_decimals_align() -> TestResult {
+    let lines = long_human_readable(&[("a.bin", 1228), ("b.bin", 15 * 1024 * 1024)])?;
+    let cells: Vec<_> = lines.iter().map(|l| &l[..size_end(l)]).collect();
+
+    // 1.2K の小数点と同じ位置で 15M の整数部が終わる
+    let point = cells[0].rfind('.').unwrap();
+    assert_eq!(&cells[0][point - 1..], "1.2K");
+    assert_eq!(&cells[1][point - 2..], "15  M");
+    Ok(())
+}
+
+/// 空白区切りで n 番目のトークンの終端のバイト位置を返す
+fn token_end(line: &str, n: usize) -> usize {
+    let token = line.split_whitespace().nth(n).unwrap();
+    token.as_ptr() as usize - line.as_ptr() as usize + token.len()
+}
+
+#[test]
+fn multiple_dirs_sections() -> TestResult {
+    let expected = "tests/inputs/dir:\n\
+                    tests/inputs/dir/spiders.txt\n\
+                    \n\
+                    tests/inputs:\n\
+                    tests/inputs/bustle.txt\n\
+                    tests/inputs/dir\n\
+                    tests/inputs/empty.txt\n\
+                    tests/inputs/fox.txt\n";
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/dir", "tests/inputs"])
+        .assert()
+        .success()
+        .stdout(expected);
+    Ok(())
+}
+
+#[test]
+fn no_group_blank_lines() -> TestResult {
+    let expected = "tests/inputs/dir:\n\
+                    tests/inputs/dir/spiders.txt\n\
+                    tests/inputs:\n\
+                    tests/inputs/bustle.txt\n\
+                    tests/inputs/dir\n\
+                    tests/inputs/empty.txt\n\
+                    tests/inputs/fox.txt\n";
+    Command::cargo_bin(PRG)?
+        .args(["--no-group-blank-lines", "tests/inputs/dir", "tests/inputs"])
+        .assert()
+        .success()
+        .stdout(expected);
+    Ok(())
+}
+
+#[test]
+fn tree_style() -> TestResult {
+    let expected = "tests/inputs\n\
+                    |-- bustle.txt\n\
+                    |-- dir\n\
+                    |   `-- spiders.txt\n\
+                    |-- empty.txt\n\
+                    `-- fox.txt\n";
+    Command::cargo_bin(PRG)?
+        .args(["--tree", "--tree-style=ascii", "tests/inputs"])
+        .assert()
+        .success()
+        .stdout(expected);
+
+    let expected = "tests/inputs\n\
+                    ├── bustle.txt\n\
+                    ├── dir\n\
+                    │   └── spiders.txt\n\
+                    ├── empty.txt\n\
+                    └── fox.txt\n";
+    Command::cargo_bin(PRG)?
+        .args(["--tree", "tests/inputs"])
+        .assert()
+        .success()
+        .stdout(expected);
+    Ok(())
+}
+
+#[test]
+fn tree_bad_root() -> TestResult {
+    // 存在しない根は表示せず、エラーを報告して残りの根を続ける
+    let bad = gen_bad_file();
+    let expected = format!("{}: No such file or directory (os error 2)", &bad);
+    Command::cargo_bin(PRG)?
+        .args(["--tree", "--tree-style=ascii", &bad, "tests/inputs/dir"])
+        .assert()
+        .success()
+        .stdout("tests/inputs/dir\n`-- spiders.txt\n")
+        .stderr(predicate::str::contains(expected));
+    Ok(())
+}
+
+#[test]
+fn tree_unreadable_subdir() -> TestResult {
+    // root はパーミッションに関係なく読めてしまうので確かめられない
+    if users::get_current_uid() == 0 {
+        return Ok(());
+    }
+
+    let dir = gen_temp_dir();
+    fs::create_dir_all(dir.join("a"))?;
+    fs::create_dir_all(dir.join("b"))?;
+    fs::write(dir.join("b/file"), "")?;
+    fs::set_permissions(dir.join("a"), fs::Permissions::from_mode(0o000))?;
+
+    let output = Command::cargo_bin(PRG)?
+        .args(["--tree", "--tree-style=ascii"])
+        .arg(&dir)
+        .output()?;
+    fs::set_permissions(dir.join("a"), fs::Permissions::from_mode(0o755))?;
+    fs::remove_dir_all(&dir)?;
+
+    // 読めないディレクトリの名前を表示し、続くディレクトリも辿る
+    let d = dir.display();
+    let expected = format!("{d}\n|-- a\n`-- b\n    `-- file\n");
+    assert_eq!(String::from_utf8(output.stdout)?, expected);
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.starts_with(&format!("{d}/a: Permission denied")));
+    Ok(())
+}
+
+#[test]
+fn long_inode_right_aligned() -> TestResult {
+    let cmd = Command::cargo_bin(PRG)?
+        .args(["-li", "tests/inputs", "tests/inputs/dir"])
+        .assert()
+        .success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+
+    // 区切りごとに inode 番号の末尾がそろう。見出しの次に total の行が続く
+    for section in stdout.split("\n\n") {
+        let lines: Vec<&str> = section.lines().skip(1).collect();
+        assert!(lines[0].starts_with("total "), "{}", section);
+        let lines = &lines[1..];
+        assert!(!lines.is_empty());
+        for line in lines {
+            let path = line.split_whitespace().last().unwrap();
+            let inode = line.split_whitespace().next().unwrap();
+            assert_eq!(inode, fs::symlink_metadata(path)?.ino().to_string());
+        }
+        let ends: Vec<_> = lines.iter().map(|l| token_end(l, 0)).collect();
+        assert!(ends.windows(2).all(|w| w[0] == w[1]));
+    }
+    Ok(())
+}
+
+#[test]
+fn help_json() -> TestResult {
+    let cmd = Command::cargo_bin(PRG)?
+        .arg("--help=json")
+        .assert()
+        .success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+    let json = stdout.trim_end();
+    assert!(json.starts_with(r#"{"name":"lsr","#));
+    assert!(json.ends_with("]}"));
+
+    // --long は短い名前 -l とともに含まれる
+    assert!(json.contains(
+        r#"{"id":"long","long":"long","short":"l","aliases":[],"short_aliases":[],"help":"Long listing","#
+    ));
+    Ok(())
+}
+
+#[test]
+fn generate_completions() -> TestResult {
+    let cmd = Command::cargo_bin(PRG)?
+        .arg("--generate-completions=bash")
+        .assert()
+        .success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+    assert!(stdout.contains("complete -F _lsr "));
+    // lsr の後に続けられるオプションの一覧に clap の定義がそのまま入る
+    let opts = stdout
+        .lines()
+        .map(|l| l.trim())
+        .find(|l| l.starts_with("opts=\"-"))
+        .unwrap();
+    let opts: Vec<_> = opts["opts=".len()..].trim_matches('"').split(' ').collect();
+    for flag in ["-l", "--long", "-x", "--format", "-a", "--all", "--color"] {
+        assert!(opts.contains(&flag), "{}", flag);
+    }
+
+    for shell in ["zsh", "fish"] {
+        Command::cargo_bin(PRG)?
+            .arg(format!("--generate-completions={}", shell))
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("lsr").and(predicate::str::contains("long")));
+    }
+    Ok(())
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd"))]
+#[test]
+fn long_flags() -> TestResult {
+    let cmd = Command::cargo_bin(PRG)?
+        .args(["--long", "--flags", FOX])
+        .assert()
+        .success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+    let parts: Vec<_> = stdout.split_whitespace().collect();
+
+    // グループ名とサイズの間にフラグの列が入る
+    assert_eq!(parts.len(), 8);
+    let flags = parts.get(4).unwrap();
+    assert!(*flags == "-" || flags.chars().all(|c| c.is_ascii_lowercase() || c == ','));
+    assert_eq!(parts.get(5).unwrap(), &"45");
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn long_attrs_immutable() -> TestResult {
+    let dir = gen_temp_dir();
+    let file = dir.join("frozen");
+    fs::write(&file, "")?;
+
+    // chattr が使えない (root でない、ファイルシステムが対応していない) 環境では確かめられない
+    let chattr = |flag: &str| {
+        std::process::Command::new("chattr")
+            .arg(flag)
+            .arg(&file)
+            .status()
+            .is_ok_and(|s| s.success())
+    };
+    if !chattr("+i") {
+        fs::remove_dir_all(&dir)?;
+        return Ok(());
+    }
+    let output = Command::cargo_bin(PRG)?
+        .args(["-l", "--attrs"])
+        .arg(&file)
+        .output();
+    chattr("-i");
+    fs::remove_dir_all(&dir)?;
+
+    let stdout = String::from_utf8(output?.stdout)?;
+    assert!(stdout.starts_with("-rw-r--r--i "));
+    Ok(())
+}
+
+#[test]
+fn recursive_files_then_dirs_in_argument_order() -> TestResult {
+    let expected = "tests/inputs/bustle.txt\n\
+                    tests/inputs/fox.txt\n\
+                    \n\
+                    tests/inputs/dir:\n\
+                    tests/inputs/dir/spiders.txt\n\
+                    \n\
+                    tests/inputs:\n\
+                    tests/inputs/bustle.txt\n\
+                    tests/inputs/dir\n\
+                    tests/inputs/empty.txt\n\
+                    tests/inputs/fox.txt\n\
+                    \n\
+                    tests/inputs/dir:\n\
+                    tests/inputs/dir/spiders.txt\n";
+    Command::cargo_bin(PRG)?
+        .args(["-R", FOX, "tests/inputs/dir", BUSTLE, "tests/inputs"])
+        .assert()
+        .success()
+        .stdout(expected);
+    Ok(())
+}
+
+#[test]
+fn long_dir_size_display() -> TestResult {
+    let real = fs::metadata("tests/inputs/dir")?.len().to_string();
+    for (mode, expected) in [("real", real.as_str()), ("zero", "0"), ("dash", "-")] {
+        let cmd = Command::cargo_bin(PRG)?
+            .args([
+                "-l",
+                &format!("--dir-size-display={}", mode),
+                "tests/inputs",
+            ])
+            .assert()
+            .success();
+        let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+        let line = stdout
+            .lines()
+            .find(|l| l.ends_with("tests/inputs/dir"))
+            .unwrap();
+        let parts: Vec<_> = line.split_whitespace().collect();
+        assert_eq!(parts.get(4).unwrap(), &expected);
+
+        // ファイルのサイズはそのまま表示する
+        let line = stdout.lines().find(|l| l.ends_with(FOX)).unwrap();
+        assert_eq!(line.split_whitespace().nth(4).unwrap(), "45");
+    }
+    Ok(())
+}
+
+#[test]
+fn long_dir_count() -> TestResult {
+    let cmd = Command::cargo_bin(PRG)?
+        .args(["-l", "--dir-count", "tests/inputs"])
+        .assert()
+        .success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+
+    // tests/inputs/dir には spiders.txt と .gitkeep がある
+    let line = stdout
+        .lines()
+        .find(|l| l.ends_with("tests/inputs/dir"))
+        .unwrap();
+    assert_eq!(line.split_whitespace().nth(4).unwrap(), "1");
+
+    let line = stdout.lines().find(|l| l.ends_with(FOX)).unwrap();
+    assert_eq!(line.split_whitespace().nth(4).unwrap(), "45");
+
+    // -a の場合は隠しファイルも数える
+    let cmd = Command::cargo_bin(PRG)?
+        .args(["-la", "--dir-count", "tests/inputs"])
+        .assert()
+        .success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+    let line = stdout
+        .lines()
+        .find(|l| l.ends_with("tests/inputs/dir"))
+        .unwrap();
+    assert_eq!(line.split_whitespace().nth(4).unwrap(), "2");
+    Ok(())
+}
+
+#[test]
+fn color_legend() -> TestResult {
+    let cmd = Command::cargo_bin(PRG)?
+        .env("LS_COLORS", "di=01;35")
+        .args(["--color=always", "--color-legend"])
+        .assert()
+        .success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+    assert!(stdout.contains("\x1b[01;35mdirectory\x1b[0m\n"));
+    assert!(stdout.contains("\x1b[01;36msymlink\x1b[0m\n"));
+    assert!(stdout.contains("\x1b[01;32mexecutable\x1b[0m\n"));
+
+    // 色を付けない場合は種別の名前だけを並べる
+    Command::cargo_bin(PRG)?
+        .arg("--color-legend")
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("directory\nsymlink\n"));
+    Ok(())
+}
+
+#[test]
+fn recursive_all_terminates() -> TestResult {
+    let expected = "tests/inputs:\n\
+                    tests/inputs/.hidden\n\
+                    tests/inputs/bustle.txt\n\
+                    tests/inputs/dir\n\
+                    tests/inputs/empty.txt\n\
+                    tests/inputs/fox.txt\n\
+                    \n\
+                    tests/inputs/dir:\n\
+                    tests/inputs/dir/.gitkeep\n\
+                    tests/inputs/dir/spiders.txt\n";
+    Command::cargo_bin(PRG)?
+        .args(["-aR", "tests/inputs"])
+        .timeout(std::time::Duration::from_secs(10))
+        .assert()
+        .success()
+        .stdout(expected);
+    Ok(())
+}
+
+#[test]
+fn format_across() -> TestResult {
+    let expected = "tests/inputs/bustle.txt  tests/inputs/dir\n\
+                    tests/inputs/empty.txt   tests/inputs/fox.txt\n";
+    Command::cargo_bin(PRG)?
+        .args(["-x", "--width=50", "tests/inputs"])
+        .assert()
+        .success()
+        .stdout(expected);
+
+    let expected = "tests/inputs/bustle.txt  tests/inputs/dir  \
+                    tests/inputs/empty.txt  tests/inputs/fox.txt\n";
+    Command::cargo_bin(PRG)?
+        .env("COLUMNS", "100")
+        .args(["--format=across", "tests/inputs"])
+        .assert()
+        .success()
+        .stdout(expected);
+    Ok(())
+}
+
+#[test]
+fn format_last_wins() -> TestResult {
+    let expected = "tests/inputs/bustle.txt\n\
+                    tests/inputs/dir\n\
+                    tests/inputs/empty.txt\n\
+                    tests/inputs/fox.txt\n";
+    for args in [["-l", "--format=single"], ["-x", "--format=single-column"]] {
+        Command::cargo_bin(PRG)?
+            .args(args)
+            .arg("tests/inputs")
+            .assert()
+            .success()
+            .stdout(expected);
+    }
+
+    // --format=long は -l と同じ
+    let long = Command::cargo_bin(PRG)?.args(["-l", FOX]).output()?;
+    Command::cargo_bin(PRG)?
+        .args(["-x", "--format=long", FOX])
+        .assert()
+        .success()
+        .stdout(long.stdout);
+    Ok(())
+}
+
+#[test]
+fn recursive_json_flat() -> TestResult {
+    let cmd = Command::cargo_bin(PRG)?
+        .args(["-R", "--json-flat", "tests/inputs"])
+        .assert()
+        .success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+    let json = stdout.trim_end();
+
+    // トップレベルのキーはディレクトリのパスになる
+    assert!(json.starts_with(r#"{"tests/inputs":[{"name":"bustle.txt","path":"tests/inputs/bustle.txt","type":"file","mode":"rw-r--r--","#));
+    assert!(json.contains(r#"{"name":"dir","path":"tests/inputs/dir","type":"directory","#));
+    assert!(json.contains(r#"],"tests/inputs/dir":[{"name":"spiders.txt","path":"tests/inputs/dir/spiders.txt","type":"file","#));
+    assert!(json.ends_with("}]}"));
+    assert_eq!(json.matches(":[").count(), 2);
+    Ok(())
+}
+
+#[test]
+fn owner_group_filters() -> TestResult {
+    let user = users::get_current_username().unwrap();
+    let group = users::get_current_groupname().unwrap();
+    let all = [
+        "tests/inputs/bustle.txt",
+        "tests/inputs/dir",
+        "tests/inputs/empty.txt",
+        "tests/inputs/fox.txt",
+    ];
+
+    // チェックアウトしたユーザーのファイルはすべて残る
+    dir_short(&["--owner", user.to_str().unwrap(), "tests/inputs"], &all)?;
+    dir_short(&["--group", group.to_str().unwrap(), "tests/inputs"], &all)?;
+
+    // 誰も使っていない ID では何も残らない
+    dir_short(&["--owner=4000000000", "tests/inputs"], &[])?;
+
+    Command::cargo_bin(PRG)?
+        .args(["--owner=no-such-user-lsr", "tests/inputs"])
+        .assert()
+        .failure()
+        .stderr("no-such-user-lsr: no such user");
+    Ok(())
+}
+
+#[test]
+fn uid_gid_filters() -> TestResult {
+    let uid = users::get_current_uid().to_string();
+    let gid = users::get_current_gid().to_string();
+    let all = [
+        "tests/inputs/bustle.txt",
+        "tests/inputs/dir",
+        "tests/inputs/empty.txt",
+        "tests/inputs/fox.txt",
+    ];
+
+    // 数値の ID をそのまま比べる
+    dir_short(&["--uid", &uid, "tests/inputs"], &all)?;
+    dir_short(&["--gid", &gid, "tests/inputs"], &all)?;
+    dir_short(&["--uid=4000000000", "tests/inputs"], &[])?;
+    dir_short(&["--gid=4000000000", "tests/inputs"], &[])?;
+    Ok(())
+}
+
+#[test]
+fn recursive_max_entries_total() -> TestResult {
+    // 3 件に達した時点でサブディレクトリを辿らずに打ち切る
+    let expected = "tests/inputs:\n\
+                    tests/inputs/bustle.txt\n\
+                    tests/inputs/dir\n\
+                    tests/inputs/empty.txt\n";
+    Command::cargo_bin(PRG)?
+        .args(["-R", "--max-entries-total=3", "tests/inputs"])
+        .assert()
+        .success()
+        .stdout(expected)
+        .stderr("lsr: listing truncated after 3 entries\n");
+
+    // --max-entries はディレクトリごとの上限になる
+    let expected = "tests/inputs:\n\
+                    tests/inputs/bustle.txt\n\
+                    \n\
+                    tests/inputs/dir:\n\
+                    tests/inputs/dir/spiders.txt\n";
+    Command::cargo_bin(PRG)?
+        .args(["-R", "--max-entries=1", "tests/inputs"])
+        .assert()
+        .success()
+        .stdout(expected)
+        .stderr("");
+    Ok(())
+}
+
+#[test]
+fn color_size_scale_breaks() -> TestResult {
+    let dir = gen_temp_dir();
+    let file = dir.join("large.bin");
+    fs::File::create(&file)?.set_len(2 * 1024 * 1024)?;
+    let output = Command::cargo_bin(PRG)?
+        .args(["-l", "--color=always", "--size-scale-breaks=1K,1M,1G"])
+        .arg(&file)
+        .output()?;
+    fs::remove_dir_all(&dir)?;
+
+    // 1M 以上 1G 未満の区間の色が付く
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains(" \x1b[31m2097152\x1b[0m "));
+    Ok(())
+}
+
+#[test]
+fn recursive_exclude() -> TestResult {
+    let output = Command::cargo_bin(PRG)?
+        .args(["-R", "--exclude=*.txt", "tests/inputs"])
+        .output()?;
+    let stdout = String::from_utf8(output.stdout)?;
+
+    // ディレクトリは辿るが、どの階層でも txt ファイルは表示しない
+    assert!(stdout.contains("tests/inputs/dir:"));
+    assert!(stdout.lines().all(|l| !l.ends_with(".txt")));
+    Ok(())
+}
+
+#[test]
+fn color_detect_scripts() -> TestResult {
+    let dir = gen_temp_dir();
+    let file = dir.join("script");
+    fs::write(&file, "#!/bin/sh\necho hello\n")?;
+    fs::set_permissions(&file, fs::Permissions::from_mode(0o644))?;
+
+    let plain = Command::cargo_bin(PRG)?
+        .args(["--color=always"])
+        .arg(&file)
+        .output()?;
+    let detected = Command::cargo_bin(PRG)?
+        .args(["--color=always", "--detect-scripts"])
+        .arg(&file)
+        .output()?;
+    fs::remove_dir_all(&dir)?;
+
+    let name = file.display().to_string();
+    assert_eq!(String::from_utf8(plain.stdout)?, format!("{}\n", name));
+    assert_eq!(
+        String::from_utf8(detected.stdout)?,
+        format!("\x1b[01;32m{}\x1b[0m\n", name)
+    );
+    Ok(())
+}
+
+#[test]
+fn long_no_time() -> TestResult {
+    let cmd = Command::cargo_bin(PRG)?
+        .args(["--long", "--no-time", FOX])
+        .assert()
+        .success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+    let parts: Vec<_> = stdout.split_whitespace().collect();
+
+    // 時刻の列がなく、サイズの直後にパスが続く
+    assert_eq!(parts.len(), 6);
+    assert!(parts.iter().all(|p| !p.contains(':')));
+    assert_eq!(&parts[4..], &["45", FOX]);
+    Ok(())
+}
+
+#[test]
+fn recursive_group_directories_first() -> TestResult {
+    let dir = gen_temp_dir();
+    fs::create_dir_all(dir.join("b"))?;
+    fs::create_dir_all(dir.join("d/z"))?;
+    fs::write(dir.join("a.txt"), "")?;
+    fs::write(dir.join("c.txt"), "")?;
+    fs::write(dir.join("d/x.txt"), "")?;
+    fs::write(dir.join("d/y.txt"), "")?;
+
+    let output = Command::cargo_bin(PRG)?
+        .args(["-R", "--group-directories-first"])
+        .arg(&dir)
+        .output()?;
+    fs::remove_dir_all(&dir)?;
+
+    // 区切りごとにディレクトリが先に、それぞれ名前順で並ぶ
+    let d = dir.display();
+    let expected = format!(
+        "{d}:\n{d}/b\n{d}/d\n{d}/a.txt\n{d}/c.txt\n\n\
+         {d}/b:\n\n\
+         {d}/d:\n{d}/d/z\n{d}/d/x.txt\n{d}/d/y.txt\n\n\
+         {d}/d/z:\n"
+    );
+    assert_eq!(String::from_utf8(output.stdout)?, expected);
+    Ok(())
+}
+
+#[test]
+fn recursive_unreadable_subdir() -> TestResult {
+    // root はパーミッションに関係なく読めてしまうので確かめられない
+    if users::get_current_uid() == 0 {
+        return Ok(());
+    }
+
+    let dir = gen_temp_dir();
+    fs::create_dir_all(dir.join("a"))?;
+    fs::create_dir_all(dir.join("b"))?;
+    fs::write(dir.join("b/file"), "")?;
+    fs::set_permissions(dir.join("a"), fs::Permissions::from_mode(0o000))?;
+
+    let output = Command::cargo_bin(PRG)?.arg("-R").arg(&dir).output()?;
+    fs::set_permissions(dir.join("a"), fs::Permissions::from_mode(0o755))?;
+    fs::remove_dir_all(&dir)?;
+
+    // 読めないディレクトリの見出しを表示し、続くディレクトリも表示する
+    let d = dir.display();
+    let expected = format!("{d}:\n{d}/a\n{d}/b\n\n{d}/a:\n\n{d}/b:\n{d}/b/file\n");
+    assert_eq!(String::from_utf8(output.stdout)?, expected);
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.starts_with(&format!("{d}/a: Permission denied")));
+    Ok(())
+}
+
+#[test]
+fn color_special_bits() -> TestResult {
+    let dir = gen_temp_dir();
+    for (name, mode) in [("setuid", 0o4755), ("setgid", 0o2755), ("sticky", 0o1644)] {
+        let file = dir.join(name);
+        fs::write(&file, "")?;
+        fs::set_permissions(&file, fs::Permissions::from_mode(mode))?;
+    }
+    let output = Command::cargo_bin(PRG)?
+        .env("LS_COLORS", "su=01;31:st=01;34")
+        .args(["--color=always"])
+        .arg(&dir)
+        .output()?;
+    fs::remove_dir_all(&dir)?;
+
+    // 実行ビットより特殊なビットの色を優先し、指定のないものは既定値を使う
+    let d = dir.display();
+    let expected = format!(
+        "\x1b[30;43m{d}/setgid\x1b[0m\n\
+         \x1b[01;31m{d}/setuid\x1b[0m\n\
+         \x1b[01;34m{d}/sticky\x1b[0m\n"
+    );
+    assert_eq!(String::from_utf8(output.stdout)?, expected);
+    Ok(())
+}
+
+#[test]
+fn long_pad_with_zeros() -> TestResult {
+    let cmd = Command::cargo_bin(PRG)?
+        .args(["-l", "--pad-with-zeros", "tests/inputs"])
+        .assert()
+        .success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+
+    // 列の中で最も長い値の幅まで 0 で埋める
+    let size = |name: &str| {
+        let line = stdout.lines().find(|l| l.ends_with(name)).unwrap();
+        line.split_whitespace().nth(4).unwrap().to_string()
+    };
+    let dir_size = fs::metadata("tests/inputs/dir")?.len().to_string();
+    let width = dir_size.len().max(3);
+    assert_eq!(size(FOX), format!("{:0>w$}", 45, w = width));
+    assert_eq!(size(EMPTY), "0".repeat(width));
+    assert_eq!(size(BUSTLE), format!("{:0>w$}", 193, w = width));
+    Ok(())
+}
+
+#[test]
+fn long_age_column() -> TestResult {
+    let dir = gen_temp_dir();
+    let file = dir.join("new.txt");
+    fs::write(&file, "")?;
+    let output = Command::cargo_bin(PRG)?
+        .args(["-l", "--age-column"])
+        .arg(&file)
+        .output()?;
+    fs::remove_dir_all(&dir)?;
+
+    // 時刻の列の後に経過時間の列が続く
+    let stdout = String::from_utf8(output.stdout)?;
+    let parts: Vec<_> = stdout.split_whitespace().collect();
+    assert_eq!(parts.len(), 8);
+    let (hour, minute) = parts[5].split_once(':').unwrap();
+    assert!(hour.parse::<u32>()? < 24);
+    assert!(minute.parse::<u32>()? < 60);
+    let age = parts[6].strip_suffix('s').unwrap();
+    assert!(age.parse::<u32>()? < 60);
+    Ok(())
+}
+
+#[test]
+fn recursive_depth_first_immediate() -> TestResult {
+    let dir = gen_temp_dir();
+    fs::create_dir_all(dir.join("a/b"))?;
+    fs::write(dir.join("a/b/file"), "")?;
+    fs::write(dir.join("top"), "")?;
+    let run = |args: &[&str]| -> Result<String, Box<dyn Error>> {
+        let output = Command::cargo_bin(PRG)?.args(args).arg(&dir).output()?;
+        Ok(String::from_utf8(output.stdout)?)
+    };
+    let entries_first = run(&["-R"])?;
+    let immediate = run(&["-R", "--depth-first-immediate"])?;
+    fs::remove_dir_all(&dir)?;
+
+    let d = dir.display();
+    assert_eq!(
+        entries_first,
+        format!("{d}:\n{d}/a\n{d}/top\n\n{d}/a:\n{d}/a/b\n\n{d}/a/b:\n{d}/a/b/file\n")
+    );
+    // サブディレクトリを辿り終えてから親のエントリを表示する
+    assert_eq!(
+        immediate,
+        format!("{d}/a/b:\n{d}/a/b/file\n\n{d}/a:\n{d}/a/b\n\n{d}:\n{d}/a\n{d}/top\n")
+    );
+    Ok(())
+}
+
+#[test]
+fn long_block_size_env() -> TestResult {
+    let dir = gen_temp_dir();
+    let file = dir.join("large.bin");
+    fs::File::create(&file)?.set_len(3 * 1024 * 1024 + 1)?;
+    let size = |envs: &[(&str, &str)], args: &[&str]| -> Result<String, Box<dyn Error>> {
+        let output = Command::cargo_bin(PRG)?
+            .env_remove("LS_BLOCK_SIZE")
+            .env_remove("BLOCK_SIZE")
+            .env_remove("BLOCKSIZE")
+            .envs(envs.iter().copied())
+            .arg("-l")
+            .args(args)
+            .arg(&file)
+            .output()?;
+        let stdout = String::from_utf8(output.stdout)?;
+        Ok(stdout.split_whitespace().nth(4).unwrap().to_string())
+    };
+
+    // 1M 単位で切り上げる
+    assert_eq!(size(&[("BLOCK_SIZE", "1M")], &[])?, "4");
+    assert_eq!(size(&[("BLOCKSIZE", "1K")], &[])?, "3073");
+    // LS_BLOCK_SIZE が優先され、オプションはさらに優先される
+    assert_eq!(
+        size(&[("LS_BLOCK_SIZE", "1K"), ("BLOCK_SIZE", "1M")], &[])?,
+        "3073"
+    );
+    assert_eq!(
+        size(&[("BLOCK_SIZE", "1M")], &["--block-size=1"])?,
+        "3145729"
+    );
+    assert_eq!(size(&[("BLOCK_SIZE", "1M")], &["-h"])?, "3.1M");
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn posix_defaults() -> TestResult {
+    let dir = gen_temp_dir();
+    for name in ["a", "B", "c"] {
+        fs::write(dir.join(name), "x".repeat(4096))?;
+    }
+    let output = Command::cargo_bin(PRG)?
+        .args(["-s", "--posix"])
+        .arg(&dir)
+        .output()?;
+    let blocks = fs::metadata(dir.join("a"))?.blocks();
+    fs::remove_dir_all(&dir)?;
+
+    // バイト順に並べ、ブロックは 512 バイト単位で数える
+    let d = dir.display();
+    let expected = format!("{blocks} {d}/B\n{blocks} {d}/a\n{blocks} {d}/c\n");
+    assert_eq!(String::from_utf8(output.stdout)?, expected);
+
+    // 時刻は伝統的な形式で表示する
+    let cmd = Command::cargo_bin(PRG)?
+        .args(["-l", "--posix", FOX])
+        .assert()
+        .success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+    let parts: Vec<_> = stdout.split_whitespace().collect();
+    assert_eq!(parts.len(), 9);
+    assert!(parts[6].parse::<u32>()? <= 31);
+    Ok(())
+}
+
+#[test]
+fn executable_filters() -> TestResult {
+    let dir = gen_temp_dir();
+    fs::create_dir(dir.join("sub"))?;
+    for (name, mode) in [("run", 0o755), ("data", 0o644), ("others", 0o601)] {
+        let file = dir.join(name);
+        fs::write(&file, "")?;
+        fs::set_permissions(&file, fs::Permissions::from_mode(mode))?;
+    }
+    let run = |flag: &str| -> Result<String, Box<dyn Error>> {
+        let output = Command::cargo_bin(PRG)?.arg(flag).arg(&dir).output()?;
+        Ok(String::from_utf8(output.stdout)?)
+    };
+    let executable = run("--executable")?;
+    let executable_files = run("--executable-files")?;
+    fs::remove_dir_all(&dir)?;
+
+    // どれか 1 つでも実行ビットがあれば残す。--executable-files はディレクトリを除く
+    let d = dir.display();
+    assert_eq!(executable, format!("{d}/others\n{d}/run\n{d}/sub\n"));
+    assert_eq!(executable_files, format!("{d}/others\n{d}/run\n"));
+    Ok(())
+}
+
+#[test]
+fn long_show_hardlinks() -> TestResult {
+    let dir = gen_temp_dir();
+    fs::write(dir.join("a"), "")?;
+    fs::hard_link(dir.join("a"), dir.join("c"))?;
+    fs::write(dir.join("b"), "")?;
+    fs::write(dir.join("d"), "")?;
+    fs::hard_link(dir.join("d"), dir.join("e"))?;
+    // シンボリックリンクはリンク先と同じ inode を指してもハードリンクではない
+    symlink("a", dir.join("s"))?;
+    fs::create_dir(dir.join("t"))?;
+    symlink("t", dir.join("u"))?;
+    let output = Command::cargo_bin(PRG)?
+        .args(["-l", "--show-hardlinks"])
+        .arg(&dir)
+        .output()?;
+    fs::remove_dir_all(&dir)?;
+
+    // 同じファイルへのリンクには同じ番号を、現れた順に振る
+    let stdout = String::from_utf8(output.stdout)?;
+    let names: Vec<_> = long_entries(&stdout)
+        .into_iter()
+        .map(|l| l.split_once(&*dir.to_string_lossy()).unwrap().1)
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "/a [hardlink group 1]",
+            "/b",
+            "/c [hardlink group 1]",
+            "/d [hardlink group 2]",
+            "/e [hardlink group 2]",
+            "/s",
+            "/t",
+            "/u",
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn tree_max_depth_summary() -> TestResult {
+    let dir = gen_temp_dir();
+    fs::create_dir_all(dir.join("a/b/c"))?;
+    fs::write(dir.join("a/b/deep.txt"), "")?;
+    fs::write(dir.join("a/inner.txt"), "")?;
+    fs::write(dir.join("top.txt"), "")?;
+    let run = |depth: &str| -> Result<String, Box<dyn Error>> {
+        let output = Command::cargo_bin(PRG)?
+            .args(["--tree", "--tree-style=ascii", "--max-depth", depth])
+            .arg(&dir)
+            .output()?;
+        Ok(String::from_utf8(output.stdout)?)
+    };
+    let shallow = run("1")?;
+    let deep = run("5")?;
+    fs::remove_dir_all(&dir)?;
+
+    // 表示したエントリだけを数える
+    let d = dir.display();
+    assert_eq!(
+        shallow,
+        format!("{d}\n|-- a\n`-- top.txt\n\n1 directories, 1 files\n")
+    );
+    assert_eq!(
+        deep,
+        format!(
+            "{d}\n\
+             |-- a\n\
+             |   |-- b\n\
+             |   |   |-- c\n\
+             |   |   `-- deep.txt\n\
+             |   `-- inner.txt\n\
+             `-- top.txt\n\
+             \n\
+             3 directories, 3 files\n"
+        )
+    );
+    Ok(())
+}
+
+#[test]
+fn long_normalize_special_sizes() -> TestResult {
+    let dir = gen_temp_dir();
+    let socket = dir.join("sock");
+    let _listener = std::os::unix::net::UnixListener::bind(&socket)?;
+    let fifo = dir.join("fifo");
+    let has_fifo = std::process::Command::new("mkfifo")
+        .arg(&fifo)
+        .status()
+        .is_ok_and(|s| s.success());
+
+    let output = Command::cargo_bin(PRG)?
+        .args(["-l", "--normalize-special-sizes"])
+        .arg(&dir)
+        .output()?;
+    fs::remove_dir_all(&dir)?;
+
+    let stdout = String::from_utf8(output.stdout)?;
+    let lines = long_entries(&stdout);
+    assert_eq!(lines.len(), 1 + has_fifo as usize);
+    for line in lines {
+        assert_eq!(line.split_whitespace().nth(4).unwrap(), "0");
+    }
+    Ok(())
+}
+
+#[test]
+fn sort_random_seed() -> TestResult {
+    let expected = "tests/inputs/dir\n\
+                    tests/inputs/empty.txt\n\
+                    tests/inputs/bustle.txt\n\
+                    tests/inputs/fox.txt\n";
+    // 同じ種なら何度実行しても同じ並びになる
+    for _ in 0..3 {
+        Command::cargo_bin(PRG)?
+            .args(["--sort=random", "--seed=7", "tests/inputs"])
+            .assert()
+            .success()
+            .stdout(expected);
+    }
+    Ok(())
+}
+
+#[test]
+fn long_device() -> TestResult {
+    let cmd = Command::cargo_bin(PRG)?
+        .args(["-l", "--device", "tests/inputs"])
+        .assert()
+        .success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+
+    // 同じディレクトリのファイルは同じファイルシステムにある
+    let dev = fs::metadata("tests/inputs")?.dev().to_string();
+    let lines = long_entries(&stdout);
+    assert_eq!(lines.len(), 4);
+    for line in lines {
+        assert_eq!(line.split_whitespace().next().unwrap(), dev);
+    }
+    Ok(())
+}
+
+#[test]
+fn newer_older_filters() -> TestResult {
+    let dir = gen_temp_dir();
+    let now = std::time::SystemTime::now();
+    for (name, age) in [("old", 300), ("ref", 200), ("new", 100)] {
+        let file = fs::File::create(dir.join(name))?;
+        file.set_modified(now - std::time::Duration::from_secs(age))?;
+    }
+    let reference = dir.join("ref");
+    let run = |flag: &str| -> Result<String, Box<dyn Error>> {
+        let output = Command::cargo_bin(PRG)?
+            .arg(format!("{}={}", flag, reference.display()))
+            .arg(&dir)
+            .output()?;
+        Ok(String::from_utf8(output.stdout)?)
+    };
+    let newer = run("--newer")?;
+    let older = run("--older")?;
+    let exclude_newer = run("--exclude-newer")?;
+    let exclude_older = run("--exclude-older")?;
+    fs::remove_dir_all(&dir)?;
+
+    // --exclude-* は基準と同じ時刻のものを残す
+    let d = dir.display();
+    assert_eq!(newer, format!("{d}/new\n"));
+    assert_eq!(older, format!("{d}/old\n"));
+    assert_eq!(exclude_newer, format!("{d}/old\n{d}/ref\n"));
+    assert_eq!(exclude_older, format!("{d}/new\n{d}/ref\n"));
+
+    Command::cargo_bin(PRG)?
+        .args(["--newer=tests/inputs/no-such-file", "tests/inputs"])
+        .assert()
+        .failure();
+    Ok(())
+}
+
+#[test]
+fn color_indicator_inside_span() -> TestResult {
+    let dir = gen_temp_dir();
+    fs::create_dir(dir.join("sub"))?;
+    let exe = dir.join("run");
+    fs::write(&exe, "")?;
+    fs::set_permissions(&exe, fs::Permissions::from_mode(0o755))?;
+    fs::write(dir.join("plain"), "")?;
+    let run = |flag: &str| -> Result<String, Box<dyn Error>> {
+        let output = Command::cargo_bin(PRG)?
+            .env_remove("LS_COLORS")
+            .args(["--color=always", flag])
+            .arg(&dir)
+            .output()?;
+        Ok(String::from_utf8(output.stdout)?)
+    };
+    let slash = run("-p")?;
+    let classify = run("-F")?;
+    fs::remove_dir_all(&dir)?;
+
+    // 印は色のリセットより前に付ける
+    let d = dir.display();
+    assert_eq!(
+        slash,
+        format!("{d}/plain\n\x1b[01;32m{d}/run\x1b[0m\n\x1b[01;34m{d}/sub/\x1b[0m\n")
+    );
+    assert_eq!(
+        classify,
+        format!("{d}/plain\n\x1b[01;32m{d}/run*\x1b[0m\n\x1b[01;34m{d}/sub/\x1b[0m\n")
+    );
+    Ok(())
+}
+
+#[test]
+fn collapse_below() -> TestResult {
+    let dir = gen_temp_dir();
+    for (name, size) in [("a", 10), ("b", 20), ("big", 2048), ("c", 30)] {
+        fs::write(dir.join(name), "x".repeat(size))?;
+    }
+    fs::create_dir(dir.join("sub"))?;
+    let output = Command::cargo_bin(PRG)?
+        .arg("--collapse-below=1K")
+        .arg(&dir)
+        .output()?;
+    fs::remove_dir_all(&dir)?;
+
+    // 小さいファイルは 1 行にまとめ、ディレクトリはそのまま表示する
+    let d = dir.display();
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        format!("{d}/big\n{d}/sub\n(3 files < 1024, total 60)\n")
+    );
+    Ok(())
+}
+
+#[test]
+fn long_time_style_iso_week() -> TestResult {
+    let dir = gen_temp_dir();
+    let file = dir.join("report");
+    // 2024-01-02T12:00:00Z は 2024 年の第 1 週の火曜日
+    fs::File::create(&file)?
+        .set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_704_196_800))?;
+    let output = Command::cargo_bin(PRG)?
+        .env("TZ", "UTC")
+        .args(["-l", "--time-style=iso-week"])
+        .arg(&file)
+        .output()?;
+    fs::remove_dir_all(&dir)?;
+
+    let stdout = String::from_utf8(output.stdout)?;
+    assert_eq!(stdout.split_whitespace().nth(5).unwrap(), "2024-W01-2");
+    Ok(())
+}
+
+#[test]
+fn recursive_split_output() -> TestResult {
+    let out = gen_temp_dir();
+    Command::cargo_bin(PRG)?
+        .args(["-R", "--split-output"])
+        .arg(&out)
+        .arg("tests/inputs")
+        .assert()
+        .success()
+        .stdout("");
+
+    // ディレクトリごとに、パスの "/" を "%2F" にした名前のファイルができる
+    let mut names: Vec<_> = fs::read_dir(&out)?
+        .map(|e| e.map(|e| e.file_name().to_string_lossy().to_string()))
+        .collect::<Result<_, _>>()?;
+    names.sort();
+    assert_eq!(names, ["tests%2Finputs", "tests%2Finputs%2Fdir"]);
+    assert_eq!(
+        fs::read_to_string(out.join("tests%2Finputs"))?,
+        "tests/inputs/bustle.txt\n\
+         tests/inputs/dir\n\
+         tests/inputs/empty.txt\n\
+         tests/inputs/fox.txt\n"
+    );
+    assert_eq!(
+        fs::read_to_string(out.join("tests%2Finputs%2Fdir"))?,
+        "tests/inputs/dir/spiders.txt\n"
+    );
+    fs::remove_dir_all(&out)?;
+
+    // 既定のパス "." も、先頭の "." を "%2E" にしたファイルに書き込む
+    let out = gen_temp_dir();
+    Command::cargo_bin(PRG)?
+        .current_dir("tests/inputs")
+        .args(["-R", "--split-output"])
+        .arg(&out)
+        .assert()
+        .success()
+        .stdout("");
+    let mut names: Vec<_> = fs::read_dir(&out)?
+        .map(|e| e.map(|e| e.file_name().to_string_lossy().to_string()))
+        .collect::<Result<_, _>>()?;
+    names.sort();
+    assert_eq!(names, ["%2E", "%2E%2Fdir"]);
+    assert_eq!(
+        fs::read_to_string(out.join("%2E%2Fdir"))?,
+        "./dir/spiders.txt\n"
+    );
+    fs::remove_dir_all(&out)?;
+    Ok(())
+}
+
+#[test]
+fn long_resolve_chain() -> TestResult {
+    let dir = gen_temp_dir();
+    fs::write(dir.join("file"), "")?;
+    symlink("file", dir.join("b"))?;
+    symlink("b", dir.join("a"))?;
+    symlink("loop2", dir.join("loop1"))?;
+    symlink("loop1", dir.join("loop2"))?;
+    let output = Command::cargo_bin(PRG)?
+        .args(["-l", "--resolve-chain"])
+        .arg(&dir)
+        .output()?;
+    fs::remove_dir_all(&dir)?;
+
+    let stdout = String::from_utf8(output.stdout)?;
+    let line = |name: &str| {
+        let d = dir.display().to_string();
+        let line = stdout
+            .lines()
+            .find(|l| l.contains(&format!("{}/{} ", d, name)))
+            .unwrap();
+        line[line.find(&d).unwrap()..].to_string()
+    };
+
+    // リンク先がリンクでなくなるまで辿り、ループしていれば印を付ける
+    let d = dir.display();
+    assert_eq!(line("a"), format!("{d}/a -> b -> file"));
+    assert_eq!(line("b"), format!("{d}/b -> file"));
+    assert_eq!(
+        line("loop1"),
+        format!("{d}/loop1 -> loop2 -> loop1 [cycle]")
+    );
+    Ok(())
+}
+
+#[test]
+fn recursive_min_depth() -> TestResult {
+    let expected = "tests/inputs/dir:\n\
+                    tests/inputs/dir/spiders.txt\n";
+    Command::cargo_bin(PRG)?
+        .args(["-R", "--min-depth=1", "tests/inputs"])
+        .assert()
+        .success()
+        .stdout(expected);
+
+    // 辿った先にもっと深いエントリがなければ何も表示しない
+    Command::cargo_bin(PRG)?
+        .args(["-R", "--min-depth=2", "tests/inputs", FOX])
+        .assert()
+        .success()
+        .stdout("");
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn color_capability_and_missing() -> TestResult {
+    let dir = gen_temp_dir();
+    let file = dir.join("ping");
+    fs::write(&file, "")?;
+    symlink(dir.join("nowhere"), dir.join("dangling"))?;
+    let run = |path: &PathBuf| -> Result<String, Box<dyn Error>> {
+        let output = Command::cargo_bin(PRG)?
+            .env("LS_COLORS", "ca=30;41:or=:mi=01;05;37")
+            .arg("--color=always")
+            .arg(path)
+            .output()?;
+        Ok(String::from_utf8(output.stdout)?)
+    };
+
+    // or がない場合、リンク切れには mi を使う
+    let dangling = dir.join("dangling");
+    let expected = format!(
+        "\x1b[01;05;37m{}\x1b[0m\n{}\n",
+        dangling.display(),
+        file.display()
+    );
+    assert_eq!(run(&dir)?, expected);
+
+    // setcap が使えない (root でない、ファイルシステムが対応していない) 環境では確かめられない
+    let has_setcap = std::process::Command::new("setcap")
+        .arg("cap_net_raw+ep")
+        .arg(&file)
+        .status()
+        .is_ok_and(|s| s.success());
+    if has_setcap {
+        assert_eq!(
+            run(&file)?,
+            format!("\x1b[30;41m{}\x1b[0m\n", file.display())
+        );
+    }
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn recursive_summary_per_dir() -> TestResult {
+    let expected = "tests/inputs:\n\
+                    tests/inputs/bustle.txt\n\
+                    tests/inputs/dir\n\
+                    tests/inputs/empty.txt\n\
+                    tests/inputs/fox.txt\n\
+                    3 files, 1 dirs, 238 bytes\n\
+                    \n\
+                    tests/inputs/dir:\n\
+                    tests/inputs/dir/spiders.txt\n\
+                    1 files, 0 dirs, 45 bytes\n";
+    Command::cargo_bin(PRG)?
+        .args(["-R", "--summary-per-dir", "tests/inputs"])
+        .assert()
+        .success()
+        .stdout(expected);
+    Ok(())
+}
+
+#[test]
+fn long_no_user() -> TestResult {
+    let dir = gen_temp_dir();
+    let file = dir.join("file");
+    fs::write(&file, "")?;
+    let output = Command::cargo_bin(PRG)?
+        .args(["-l", "--no-user"])
+        .arg(&file)
+        .output()?;
+    fs::remove_dir_all(&dir)?;
+
+    // リンク数の直後にグループ名が続く
+    let group = users::get_current_groupname().unwrap();
+    let stdout = String::from_utf8(output.stdout)?;
+    let parts: Vec<_> = stdout.split_whitespace().collect();
+    assert_eq!(parts.len(), 6);
+    assert_eq!(parts[2], group.to_str().unwrap());
+    assert_eq!(parts[3], "0");
+    Ok(())
+}
+
+#[test]
+fn color_scale_truecolor() -> TestResult {
+    let dir = gen_temp_dir();
+    for (name, len) in [("a", 0), ("b", 50), ("c", 100)] {
+        fs::File::create(dir.join(name))?.set_len(len)?;
+    }
+    let output = Command::cargo_bin(PRG)?
+        .env("COLORTERM", "truecolor")
+        .args(["-l", "--color=always", "--color-scale"])
+        .arg(&dir)
+        .output()?;
+    fs::remove_dir_all(&dir)?;
+
+    // 最大のサイズに対する割合で緑から赤へ補間する
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("  \x1b[38;2;0;255;0m0\x1b[0m "));
+    assert!(stdout.contains(" \x1b[38;2;128;128;0m50\x1b[0m "));
+    assert!(stdout.contains(" \x1b[38;2;255;0;0m100\x1b[0m "));
+    Ok(())
+}
+
+#[test]
+fn files_first() -> TestResult {
+    let expected = "tests/inputs/bustle.txt\n\
+                    tests/inputs/empty.txt\n\
+                    tests/inputs/fox.txt\n\
+                    tests/inputs/dir\n";
+    Command::cargo_bin(PRG)?
+        .args(["--files-first", "tests/inputs"])
+        .assert()
+        .success()
+        .stdout(expected);
+
+    Command::cargo_bin(PRG)?
+        .args(["--files-first", "--group-directories-first", "tests/inputs"])
+        .assert()
+        .failure();
+    Ok(())
+}
+
+#[test]
+fn long_compat_matches_coreutils() -> TestResult {
+    let dir = gen_temp_dir();
+    let time = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_583_298_367);
+    fs::write(dir.join("a.txt"), "hello\n")?;
+    fs::write(dir.join("big.bin"), vec![0; 12345])?;
+    fs::hard_link(dir.join("big.bin"), dir.join("big2.bin"))?;
+    for (name, mode) in [("a.txt", 0o644), ("big.bin", 0o600)] {
+        let file = fs::File::options().write(true).open(dir.join(name))?;
+        file.set_modified(time)?;
+        file.set_permissions(fs::Permissions::from_mode(mode))?;
+    }
+    let run = |args: &[PathBuf]| -> Result<String, Box<dyn Error>> {
+        let output = Command::cargo_bin(PRG)?
+            .env("TZ", "UTC")
+            .args(["-l", "--compat"])
+            .args(args)
+            .output()?;
+        Ok(String::from_utf8(output.stdout)?)
+    };
+    let files = run(&["a.txt", "big.bin", "big2.bin"].map(|n| dir.join(n)));
+    let listing = run(std::slice::from_ref(&dir));
+    let mixed = run(&[dir.join("a.txt"), dir.clone()]);
+    let blocks: u64 = ["a.txt", "big.bin", "big2.bin"]
+        .iter()
+        .map(|n| fs::metadata(dir.join(n)).map(|m| m.blocks()))
+        .sum::<std::io::Result<_>>()?;
+    let dir_size = fs::metadata(&dir)?.len().to_string();
+    fs::remove_dir_all(&dir)?;
+
+    // TZ=UTC ls -l で得たものと同じ空白の並び
+    let owner = format!(
+        "{} {}",
+        users::get_current_username().unwrap().to_string_lossy(),
+        users::get_current_groupname().unwrap().to_string_lossy()
+    );
+    let d = dir.display();
+    let entries = format!(
+        "-rw-r--r-- 1 {owner}     6 Mar  4  2020 {d}/a.txt\n\
+         -rw------- 2 {owner} 12345 Mar  4  2020 {d}/big.bin\n\
+         -rw------- 2 {owner} 12345 Mar  4  2020 {d}/big2.bin\n"
+    );
+    assert_eq!(files?, entries);
+
+    // ディレクトリの一覧には st_blocks の合計を 1 KiB 単位で表す total の行が付く
+    let total = format!("total {}\n", (blocks * 512).div_ceil(1024));
+    assert_eq!(listing?, format!("{total}{entries}"));
+
+    // 引数のファイルの列の幅には、引数のディレクトリ自身の大きさも数える
+    assert_eq!(
+        mixed?,
+        format!(
+            "-rw-r--r-- 1 {owner} {:>w$} Mar  4  2020 {d}/a.txt\n\n{d}:\n{total}{entries}",
+            6,
+            w = dir_size.len()
+        )
+    );
+    Ok(())
+}
+
+#[test]
+fn long_compat_human_inode_size() -> TestResult {
+    let dir = gen_temp_dir();
+    let time = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_583_298_367);
+    fs::write(dir.join("a"), vec![1; 193])?;
+    fs::write(dir.join("b"), vec![1; 5000])?;
+    let mut columns = vec![];
+    for name in ["a", "b"] {
+        let file = fs::File::options().write(true).open(dir.join(name))?;
+        file.set_modified(time)?;
+        file.set_permissions(fs::Permissions::from_mode(0o644))?;
+        let m = file.metadata()?;
+        columns.push((m.ino().to_string(), m.blocks()));
+    }
+    let output = Command::cargo_bin(PRG)?
+        .env("TZ", "UTC")
+        .args(["-lhis", "--compat"])
+        .args(["a", "b"].map(|n| dir.join(n)))
+        .output()?;
+    fs::remove_dir_all(&dir)?;
+
+    // -h のサイズは小数点でそろえずに右寄せし、-i と -s の列は許可の前に置く
+    assert_eq!(columns[0].1 * 512, 4096);
+    assert_eq!(columns[1].1 * 512, 8192);
+    let owner = format!(
+        "{} {}",
+        users::get_current_username().unwrap().to_string_lossy(),
+        users::get_current_groupname().unwrap().to_string_lossy()
+    );
+    let w = columns.iter().map(|(ino, _)| ino.len()).max().unwrap();
+    let d = dir.display();
+    let expected = format!(
+        "{:>w$} 4.0K -rw-r--r-- 1 {owner}  193 Mar  4  2020 {d}/a\n\
+         {:>w$} 8.0K -rw-r--r-- 1 {owner} 4.9K Mar  4  2020 {d}/b\n",
+        columns[0].0, columns[1].0
+    );
+    assert_eq!(String::from_utf8(output.stdout)?, expected);
+
+    // --compat で表せない指定は受け付けない
+    for flag in [
+        "--no-user",
+        "--no-time",
+        "--time-style=iso",
+        "--show-hardlinks",
+    ] {
+        Command::cargo_bin(PRG)?
+            .args(["-l", "--compat", flag, "tests/inputs"])
+            .assert()
+            .failure();
+    }
+    Ok(())
+}
+
+#[test]
+fn long_total_units() -> TestResult {
+    let dir = gen_temp_dir();
+    fs::write(dir.join("file"), vec![1; 12288])?;
+    let blocks = fs::metadata(dir.join("file"))?.blocks();
+    let run = |args: &[&str], env: &[(&str, &str)]| -> Result<String, Box<dyn Error>> {
+        let output = Command::cargo_bin(PRG)?
+            .env_remove("LS_BLOCK_SIZE")
+            .env_remove("BLOCK_SIZE")
+            .env_remove("BLOCKSIZE")
+            .envs(env.iter().copied())
+            .args(args)
+            .arg(&dir)
+            .output()?;
+        let stdout = String::from_utf8(output.stdout)?;
+        Ok(stdout.lines().next().unwrap_or_default().to_string())
+    };
+    let mut results = vec![];
+    for compat in [&[][..], &["--compat"][..]] {
+        let with = |args: &[&'static str]| [args, compat].concat();
+        results.push((
+            run(&with(&["-l", "--block-size=1M"]), &[])?,
+            run(&with(&["-l"]), &[("BLOCK_SIZE", "1M")])?,
+            run(&with(&["-lh"]), &[])?,
+        ));
+    }
+    fs::remove_dir_all(&dir)?;
+
+    // total の行も --block-size や BLOCK_SIZE の単位、-h の形式にそろえる
+    assert_eq!(blocks * 512, 12288);
+    for (block_size, env, human) in results {
+        assert_eq!(block_size, "total 1");
+        assert_eq!(env, "total 1");
+        assert_eq!(human, "total 12K");
+    }
+    Ok(())
+}
+
+#[test]
+fn recursive_symlink_cycle_exit_code() -> TestResult {
+    let dir = gen_temp_dir();
+    fs::create_dir(dir.join("sub"))?;
+    fs::write(dir.join("sub/file.txt"), "")?;
+    symlink("..", dir.join("sub/up"))?;
+
+    let output = Command::cargo_bin(PRG)?
+        .args(["-R", "--follow-symlinks"])
+        .arg(&dir)
+        .output()?;
+
+    // ループは 1 度だけ辿った先で見つかり、その先は表示しない
+    let d = dir.display();
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        format!("{d}:\n{d}/sub\n\n{d}/sub:\n{d}/sub/file.txt\n{d}/sub/up\n")
+    );
+    assert_eq!(
+        String::from_utf8(output.stderr)?,
+        format!("lsr: {d}/sub/up: not listing already-listed directory\n")
+    );
+    assert_eq!(output.status.code(), Some(3));
+
+    // シンボリックリンクを辿らなければループにならない
+    Command::cargo_bin(PRG)?
+        .arg("-R")
+        .arg(&dir)
+        .assert()
+        .success();
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn long_show_birth() -> TestResult {
+    let dir = gen_temp_dir();
+    let file = dir.join("born.txt");
+    fs::write(&file, "")?;
+    let supported = fs::metadata(&file)?.created().is_ok();
+
+    let output = Command::cargo_bin(PRG)?
+        .args(["-l", "--show-birth"])
+        .arg(&file)
+        .output()?;
+    fs::remove_dir_all(&dir)?;
+    assert!(output.status.success());
+
+    // 更新時刻の後に作成時刻の列が続く
+    let stdout = String::from_utf8(output.stdout)?;
+    let parts: Vec<_> = stdout.split_whitespace().collect();
+    assert_eq!(parts.len(), 8);
+    let is_time = |s: &str| s.len() == 5 && s.as_bytes()[2] == b':';
+    assert!(is_time(parts[5]));
+    if supported {
+        assert!(is_time(parts[6]), "{}", stdout);
+    } else {
+        assert_eq!(parts[6], "-");
+    }
+    Ok(())
+}
+
+#[test]
+fn warn_large() -> TestResult {
+    let expected =
+        "tests/inputs/bustle.txt\ntests/inputs/dir\ntests/inputs/empty.txt\ntests/inputs/fox.txt\n";
+
+    // tests/inputs には隠しファイルを除いて 4 つのエントリがある
+    Command::cargo_bin(PRG)?
+        .args(["--warn-large=3", "tests/inputs"])
+        .assert()
+        .success()
+        .stdout(expected)
+        .stderr("lsr: tests/inputs: listing 4 entries\n");
+
+    Command::cargo_bin(PRG)?
+        .args(["--warn-large=4", "tests/inputs"])
+        .assert()
+        .success()
+        .stdout(expected)
+        .stderr("");
+    Ok(())
+}
+
+#[test]
+fn long_created_with_modified() -> TestResult {
+    let dir = gen_temp_dir();
+    let file = dir.join("born.txt");
+    fs::write(&file, "")?;
+    let supported = fs::metadata(&file)?.created().is_ok();
+
+    let output = Command::cargo_bin(PRG)?
+        .args(["-l", "--created", "--time-style=iso-week"])
+        .arg(&file)
+        .output()?;
+    fs::remove_dir_all(&dir)?;
+    assert!(output.status.success());
+
+    // 更新時刻と作成時刻の 2 つの列が同じ形式で並ぶ
+    let stdout = String::from_utf8(output.stdout)?;
+    let parts: Vec<_> = stdout.split_whitespace().collect();
+    assert_eq!(parts.len(), 8);
+    let is_week = |s: &str| s.len() == 10 && &s[4..6] == "-W";
+    assert!(is_week(parts[5]));
+    if supported {
+        assert!(is_week(parts[6]), "{}", stdout);
+    } else {
+        assert_eq!(parts[6], "-");
+    }
+    Ok(())
+}
+
+#[test]
+fn recursive_follow_args_only() -> TestResult {
+    let dir = gen_temp_dir();
+    fs::create_dir_all(dir.join("real"))?;
+    fs::create_dir_all(dir.join("other"))?;
+    fs::write(dir.join("real/a.txt"), "")?;
+    fs::write(dir.join("other/z.txt"), "")?;
+    symlink("../other", dir.join("real/nested"))?;
+    symlink("real", dir.join("link"))?;
+    let link = dir.join("link");
+    let l = link.display();
+
+    // 引数のリンク先は展開するが、その中のシンボリックリンク先のディレクトリは辿らない
+    Command::cargo_bin(PRG)?
+        .arg("-R")
+        .arg(&link)
+        .assert()
+        .success()
+        .stdout(format!("{l}:\n{l}/a.txt\n{l}/nested\n"));
+
+    Command::cargo_bin(PRG)?
+        .args(["-R", "--follow-args-only=false"])
+        .arg(&link)
+        .assert()
+        .success()
+        .stdout(format!("{l}\n"));
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn color_theme_file() -> TestResult {
+    let dir = gen_temp_dir();
+    let theme = dir.join("theme.toml");
+    fs::write(&theme, "[types]\ndi = \"01;35\"\n")?;
+
+    // テーマの指定は LS_COLORS より優先する
+    Command::cargo_bin(PRG)?
+        .env("LS_COLORS", "di=01;33")
+        .arg("--color")
+        .arg(format!("--theme={}", theme.display()))
+        .arg("tests/inputs")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "\x1b[01;35mtests/inputs/dir\x1b[0m",
+        ));
+
+    fs::write(&theme, "[types]\ndi = purple\n")?;
+    Command::cargo_bin(PRG)?
+        .arg("--color")
+        .arg(format!("--theme={}", theme.display()))
+        .arg("tests/inputs")
+        .assert()
+        .failure()
+        .stderr(format!(
+            "{}: line 2: the color code must be a quoted string",
+            theme.display()
+        ));
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn long_relative_size() -> TestResult {
+    let dir = gen_temp_dir();
+    fs::write(dir.join("a.txt"), vec![b'a'; 30])?;
+    fs::write(dir.join("b.txt"), vec![b'b'; 10])?;
+    fs::create_dir(dir.join("sub"))?;
+    fs::write(dir.join("sub/c.txt"), vec![b'c'; 40])?;
+
+    // サイズの列の次に割合の列が続き、ディレクトリは合計に含めない
+    let shares = |extra: &[&str]| -> Result<Vec<String>, Box<dyn Error>> {
+        let output = Command::cargo_bin(PRG)?
+            .args(["-l", "--relative-size"])
+            .args(extra)
+            .arg(&dir)
+            .output()?;
+        let stdout = String::from_utf8(output.stdout)?;
+        Ok(long_entries(&stdout)
+            .iter()
+            .map(|l| l.split_whitespace().nth(5).unwrap().to_string())
+            .collect())
+    };
+    let plain = shares(&[])?;
+    assert_eq!(plain, ["75.0%", "25.0%", "-"]);
+    let total: f64 = plain
+        .iter()
+        .filter_map(|s| s.strip_suffix('%'))
+        .map(|s| s.parse::<f64>().unwrap())
+        .sum();
+    assert!((total - 100.0).abs() < 0.1);
+
+    // --du ではディレクトリの下のファイルも合計に入る
+    assert_eq!(shares(&["--du"])?, ["37.5%", "12.5%", "50.0%"]);
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn color_max_entries_ends_with_reset() -> TestResult {
+    for max in ["1", "2", "3"] {
+        let output = Command::cargo_bin(PRG)?
+            .env("LS_COLORS", "*.txt=32")
+            .args(["--color=always", "--max-entries", max, "tests/inputs"])
+            .output()?;
+        let stdout = String::from_utf8(output.stdout)?;
+        assert!(stdout.trim_end().ends_with("\x1b[0m"), "{:?}", stdout);
+    }
+    Ok(())
+}
+
+#[test]
+fn long_name_width() -> TestResult {
+    let dir = gen_temp_dir();
+    fs::write(dir.join("abcdefghijklmnop.txt"), "")?;
+    fs::write(dir.join("short"), "")?;
+
+    let output = Command::cargo_bin(PRG)?
+        .current_dir(&dir)
+        .args(["-l", "--name-width=10", "abcdefghijklmnop.txt", "short"])
+        .output()?;
+    fs::remove_dir_all(&dir)?;
+
+    let stdout = String::from_utf8(output.stdout)?;
+    let lines: Vec<_> = stdout.lines().collect();
+    assert!(lines[0].ends_with(" abcdefghi…"), "{}", stdout);
+    assert!(lines[1].ends_with(" short     "), "{}", stdout);
+    Ok(())
+}
+
+#[test]
+fn recursive_jsonl() -> TestResult {
+    let output = Command::cargo_bin(PRG)?
+        .args(["-R", "--jsonl", "tests/inputs"])
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    let lines: Vec<_> = stdout.lines().collect();
+
+    // 各行がそれぞれ 1 つのオブジェクトになる
+    for line in &lines {
+        assert!(line.starts_with('{') && line.ends_with('}'), "{}", line);
+        assert_eq!(line.matches('{').count(), 1);
+    }
+
+    // ディレクトリの行はそのエントリより前に来る
+    assert_eq!(lines.len(), 7);
+    assert_eq!(lines[0], r#"{"directory":"tests/inputs","entries":4}"#);
+    assert!(lines[1].starts_with(r#"{"name":"bustle.txt","path":"tests/inputs/bustle.txt","#));
+    assert!(lines[2].starts_with(r#"{"name":"dir","path":"tests/inputs/dir","type":"directory","#));
+    assert_eq!(lines[5], r#"{"directory":"tests/inputs/dir","entries":1}"#);
+    assert!(lines[6].starts_with(r#"{"name":"spiders.txt","path":"tests/inputs/dir/spiders.txt","#));
+    Ok(())
+}
+
+#[test]
+fn long_blocks_column() -> TestResult {
+    let dir = gen_temp_dir();
+    let file = dir.join("data.bin");
+    fs::write(&file, vec![1; 10_000])?;
+    let blocks = fs::metadata(&file)?.blocks();
+
+    // サイズの前の列にブロック数を表示し、--blocks-size の単位で数える
+    for (extra, unit) in [(None, 1024), (Some("--blocks-size=512"), 512)] {
+        let output = Command::cargo_bin(PRG)?
+            .args(["-l", "--blocks-column"])
+            .args(extra)
+            .arg(&file)
+            .output()?;
+        let stdout = String::from_utf8(output.stdout)?;
+        let parts: Vec<_> = stdout.split_whitespace().collect();
+        assert_eq!(parts[4], (blocks * 512).div_ceil(unit).to_string());
+        assert_eq!(parts[5], "10000");
+    }
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn unsorted_keeps_argument_order() -> TestResult {
+    let files = [
+        "tests/inputs/fox.txt",
+        "tests/inputs/empty.txt",
+        "tests/inputs/bustle.txt",
+    ];
+    let expected = files.join("\n") + "\n";
+
+    for flag in ["--sort=none", "-U"] {
+        Command::cargo_bin(PRG)?
+            .arg(flag)
+            .args(files)
+            .assert()
+            .success()
+            .stdout(expected.clone());
+    }
+
+    // 最後に指定したものが優先される
+    Command::cargo_bin(PRG)?
+        .arg("-U")
+        .arg("--sort=name")
+        .args(files)
+        .assert()
+        .success()
+        .stdout("tests/inputs/bustle.txt\ntests/inputs/empty.txt\ntests/inputs/fox.txt\n");
+    Ok(())
+}
+
+#[test]
+fn long_time_deltas() -> TestResult {
+    let dir = gen_temp_dir();
+    let file = dir.join("old.txt");
+    fs::write(&file, "")?;
+    let now = std::time::SystemTime::now();
+    let hour = std::time::Duration::from_secs(60 * 60);
+    fs::File::options().write(true).open(&file)?.set_times(
+        fs::FileTimes::new()
+            .set_accessed(now - 50 * hour)
+            .set_modified(now - 3 * hour),
+    )?;
+
+    let output = Command::cargo_bin(PRG)?
+        .args(["-l", "--time-deltas"])
+        .arg(&file)
+        .output()?;
+    fs::remove_dir_all(&dir)?;
+
+    // 更新時刻の後にアクセス、更新、変更からの経過時間が並ぶ。変更時刻は今なので秒になる
+    let stdout = String::from_utf8(output.stdout)?;
+    let parts: Vec<_> = stdout.split_whitespace().collect();
+    assert_eq!(parts.len(), 10);
+    assert_eq!(&parts[6..8], ["2d", "3h"]);
+    assert!(parts[8].ends_with('s'), "{}", stdout);
+    Ok(())
+}
+
+#[test]
+fn color_auto_in_pager() -> TestResult {
+    // ページャーに渡すような端末ではない出力に、auto では色を付けず、always では付ける
+    Command::cargo_bin(PRG)?
+        .env("PAGER", "less")
+        .env_remove("LESS")
+        .args(["--color=auto", "tests/inputs"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\x1b[").not());
+
+    Command::cargo_bin(PRG)?
+        .env("PAGER", "less")
+        .env_remove("LESS")
+        .args(["--color=always", "tests/inputs"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "\x1b[01;34mtests/inputs/dir\x1b[0m",
+        ));
+    Ok(())
+}
+
+#[test]
+fn long_raw_mode() -> TestResult {
+    let dir = gen_temp_dir();
+    let file = dir.join("file.txt");
+    let sub = dir.join("sub");
+    fs::write(&file, "")?;
+    fs::create_dir(&sub)?;
+    fs::set_permissions(&file, fs::Permissions::from_mode(0o644))?;
+    fs::set_permissions(&sub, fs::Permissions::from_mode(0o755))?;
+
+    let output = Command::cargo_bin(PRG)?
+        .args(["-l", "--raw-mode"])
+        .arg(&dir)
+        .output()?;
+    fs::remove_dir_all(&dir)?;
+
+    let stdout = String::from_utf8(output.stdout)?;
+    let modes: Vec<_> = long_entries(&stdout)
+        .iter()
+        .map(|l| l.split_whitespace().next().unwrap())
+        .collect();
+    assert_eq!(modes, ["100644", "040755"]);
+    Ok(())
+}
+
+#[test]
+fn long_find_duplicates() -> TestResult {
+    let dir = gen_temp_dir();
+    fs::write(dir.join("a.txt"), "same contents\n")?;
+    fs::write(dir.join("b.txt"), "other text\n")?;
+    fs::write(dir.join("c.txt"), "same contents\n")?;
+    // サイズが同じでも中身が違えば重複ではない
+    fs::write(dir.join("d.txt"), "same_contents\n")?;
+    // シンボリックリンクとハードリンクは同じ中身の別のコピーではない
+    symlink("a.txt", dir.join("e.txt"))?;
+    fs::hard_link(dir.join("c.txt"), dir.join("f.txt"))?;
+
+    let output = Command::cargo_bin(PRG)?
+        .args(["-l", "--find-duplicates"])
+        .arg(&dir)
+        .output()?;
+    fs::remove_dir_all(&dir)?;
+
+    let stdout = String::from_utf8(output.stdout)?;
+    let lines = long_entries(&stdout);
+    assert!(lines[0].ends_with("/a.txt [dup:1]"), "{}", stdout);
+    assert!(lines[1].ends_with("/b.txt"));
+    assert!(lines[2].ends_with("/c.txt [dup:1]"));
+    assert!(lines[3].ends_with("/d.txt"));
+    assert!(lines[4].ends_with("/e.txt"), "{}", stdout);
+    assert!(lines[5].ends_with("/f.txt"));
+    Ok(())
+}
+
+#[test]
+fn long_group_by_perms() -> TestResult {
+    let dir = gen_temp_dir();
+    for (name, mode) in [("a.txt", 0o644), ("b.sh", 0o755), ("c.txt", 0o644)] {
+        fs::write(dir.join(name), "")?;
+        fs::set_permissions(dir.join(name), fs::Permissions::from_mode(mode))?;
+    }
+
+    let output = Command::cargo_bin(PRG)?
+        .args(["-l", "--group-by-perms"])
+        .arg(&dir)
+        .output()?;
+    fs::remove_dir_all(&dir)?;
+
+    let stdout = String::from_utf8(output.stdout)?;
+    let lines = long_entries(&stdout);
+    assert_eq!(lines.len(), 5);
+    assert_eq!(lines[0], "rw-r--r--:");
+    assert!(lines[1].ends_with("/a.txt") && lines[2].ends_with("/c.txt"));
+    assert_eq!(lines[3], "rwxr-xr-x:");
+    assert!(lines[4].ends_with("/b.sh"));
+    Ok(())
+}
+
+#[test]
+fn color_summary_lines() -> TestResult {
+    // 集計の行は既定で太字にし、LS_COLORS の sm で変えられる
+    Command::cargo_bin(PRG)?
+        .args([
+            "--color=always",
+            "-l",
+            "--collapse-below=100",
+            "tests/inputs",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::ends_with(
+            "\x1b[01m(2 files < 100, total 45)\x1b[0m\n",
+        ));
+
+    Command::cargo_bin(PRG)?
+        .env("LS_COLORS", "sm=04;33")
+        .args(["--color=always", "--summary-per-dir", "tests/inputs/dir"])
+        .assert()
+        .success()
+        .stdout(predicate::str::ends_with(
+            "\x1b[04;33m1 files, 0 dirs, 45 bytes\x1b[0m\n",
+        ));
+
+    // ロングフォーマットのディレクトリの一覧の total の行も同じ色にする
+    let blocks = fs::metadata("tests/inputs/dir/spiders.txt")?.blocks();
+    Command::cargo_bin(PRG)?
+        .args(["--color=always", "-l", "tests/inputs/dir"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(format!(
+            "\x1b[01mtotal {}\x1b[0m\n",
+            (blocks * 512).div_ceil(1024)
+        )));
+
+    // 色を付けない場合は変わらない
+    Command::cargo_bin(PRG)?
+        .args(["-l", "--collapse-below=100", "tests/inputs"])
+        .assert()
+        .success()
+        .stdout(predicate::str::ends_with("\n(2 files < 100, total 45)\n"));
+    Ok(())
+}
+
+#[test]
+fn uri_encode_names() -> TestResult {
+    let dir = gen_temp_dir();
+    fs::write(dir.join("my file (1).txt"), "")?;
+    fs::write(dir.join("café~ß.md"), "")?;
+
+    Command::cargo_bin(PRG)?
+        .current_dir(&dir)
+        .args(["--uri-encode", "my file (1).txt", "café~ß.md"])
+        .assert()
+        .success()
+        .stdout("caf%C3%A9~%C3%9F.md\nmy%20file%20%281%29.txt\n");
+
+    // 長い形式の名前の列も同じように変える
+    Command::cargo_bin(PRG)?
+        .current_dir(&dir)
+        .args(["-l", "--uri-encode", "my file (1).txt"])
+        .assert()
+        .success()
+        .stdout(predicate::str::ends_with(" my%20file%20%281%29.txt\n"));
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn recursive_prune_empty() -> TestResult {
+    let dir = gen_temp_dir();
+    fs::create_dir_all(dir.join("empty"))?;
+    fs::create_dir_all(dir.join("hidden_only"))?;
+    fs::write(dir.join("hidden_only/.secret"), "")?;
+    fs::create_dir_all(dir.join("full"))?;
+    fs::write(dir.join("full/file.txt"), "")?;
+    let d = dir.display();
+
+    // 隠しファイルしかないディレクトリも、表示するエントリがなければ省く
+    Command::cargo_bin(PRG)?
+        .args(["-R", "--prune-empty"])
+        .arg(&dir)
+        .assert()
+        .success()
+        .stdout(format!(
+            "{d}:\n{d}/empty\n{d}/full\n{d}/hidden_only\n\n{d}/full:\n{d}/full/file.txt\n"
+        ));
+
+    let output = Command::cargo_bin(PRG)?.arg("-R").arg(&dir).output()?;
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains(&format!("{d}/empty:\n")));
+    assert!(stdout.contains(&format!("{d}/hidden_only:\n")));
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn recursive_show_depth() -> TestResult {
+    let dir = gen_temp_dir();
+    fs::create_dir_all(dir.join("sub/deeper"))?;
+    fs::write(dir.join("a.txt"), "")?;
+    fs::write(dir.join("sub/b.txt"), "")?;
+    fs::write(dir.join("sub/deeper/c.txt"), "")?;
+    let d = dir.display();
+
+    // --min-depth と同じく、引数のディレクトリの中が 0 になる
+    Command::cargo_bin(PRG)?
+        .args(["-R", "--show-depth"])
+        .arg(&dir)
+        .assert()
+        .success()
+        .stdout(format!(
+            "{d}:\n[0] {d}/a.txt\n[0] {d}/sub\n\n\
+             {d}/sub:\n[1] {d}/sub/b.txt\n[1] {d}/sub/deeper\n\n\
+             {d}/sub/deeper:\n[2] {d}/sub/deeper/c.txt\n"
+        ));
+
+    // --min-depth=1 では [0] のエントリだけを隠す
+    Command::cargo_bin(PRG)?
+        .args(["-R", "--show-depth", "--min-depth=1"])
+        .arg(&dir)
+        .assert()
+        .success()
+        .stdout(format!(
+            "{d}/sub:\n[1] {d}/sub/b.txt\n[1] {d}/sub/deeper\n\n\
+             {d}/sub/deeper:\n[2] {d}/sub/deeper/c.txt\n"
+        ));
+
+    // 引数のファイルも 0 になる
+    Command::cargo_bin(PRG)?
+        .args(["-R", "--show-depth"])
+        .arg(dir.join("a.txt"))
+        .assert()
+        .success()
+        .stdout(format!("[0] {d}/a.txt\n"));
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn sort_size_with_human_readable() -> TestResult {
+    let dir = gen_temp_dir();
+    // 表示は 900、1.2K、4.9K、10 で、文字列として並べると正しい順にならない
+    for (name, size) in [("a", 900), ("b", 1200), ("c", 5000), ("d", 10)] {
+        fs::write(dir.join(name), vec![0; size])?;
+    }
+    // シンボリックリンクは表示するリンク先の大きさで並べる
+    symlink("c", dir.join("e"))?;
+
+    for flags in [&["-l", "-h", "-S"][..], &["-l", "-h", "--sort=size"]] {
+        let output = Command::cargo_bin(PRG)?
+            .current_dir(&dir)
+            .args(flags)
+            .output()?;
+        let stdout = String::from_utf8(output.stdout)?;
+        let rows: Vec<_> = long_entries(&stdout)
+            .iter()
+            .map(|l| {
+                let parts: Vec<_> = l.split_whitespace().collect();
+                (parts[4].to_string(), parts[parts.len() - 1].to_string())
+            })
+            .collect();
+        let expected = [
+            ("4.9K", "./c"),
+            ("4.9K", "./e"),
+            ("1.2K", "./b"),
+            ("900", "./a"),
+            ("10", "./d"),
+        ]
+        .map(|(size, name)| (size.to_string(), name.to_string()));
+        assert_eq!(rows, expected, "{}", stdout);
+    }
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn color_extension_attributes() -> TestResult {
+    // 色だけでなく斜体や下線などの属性もそのまま並べて出力する
+    Command::cargo_bin(PRG)?
+        .env("LS_COLORS", "*.txt=3;32:*.md=01;04")
+        .args(["--color=always", "tests/inputs/fox.txt"])
+        .assert()
+        .success()
+        .stdout("\x1b[3;32mtests/inputs/fox.txt\x1b[0m\n");
+
+    let dir = gen_temp_dir();
+    let theme = dir.join("theme.toml");
+    fs::write(&theme, "[extensions]\ntxt = \"01;03;04;35\"\n")?;
+    Command::cargo_bin(PRG)?
+        .arg("--color=always")
+        .arg(format!("--theme={}", theme.display()))
+        .arg("tests/inputs/fox.txt")
+        .assert()
+        .success()
+        .stdout("\x1b[01;03;04;35mtests/inputs/fox.txt\x1b[0m\n");
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn long_fstype() -> TestResult {
+    // 種類の列はモードより前に来る。/proc は常に proc としてマウントされている
+    let output = Command::cargo_bin(PRG)?
+        .args(["-l", "--fstype", "/proc/self/stat"])
+        .output()?;
+    let stdout = String::from_utf8(output.stdout)?;
+    assert_eq!(stdout.split_whitespace().next(), Some("proc"), "{}", stdout);
+
+    let output = Command::cargo_bin(PRG)?
+        .args(["-l", "--fstype", "tests/inputs/fox.txt"])
+        .output()?;
+    let stdout = String::from_utf8(output.stdout)?;
+    let fstype = stdout.split_whitespace().next().unwrap();
+    assert!(
+        fstype
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.'),
+        "{}",
+        stdout
+    );
+    assert_ne!(fstype, "?");
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn long_flags_special_files() -> TestResult {
+    let dir = gen_temp_dir();
+    std::process::Command::new("mkfifo")
+        .arg(dir.join("fifo"))
+        .status()?;
+    symlink("/dev/null", dir.join("null"))?;
+
+    // FIFO やデバイスへのシンボリックリンクは開かずに - を表示する。開くと FIFO で待たされる
+    let output = Command::cargo_bin(PRG)?
+        .args(["-l", "--flags", "--attrs"])
+        .arg(&dir)
+        .timeout(std::time::Duration::from_secs(10))
+        .output()?;
+    fs::remove_dir_all(&dir)?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    let flags: Vec<_> = long_entries(&stdout)
+        .iter()
+        .map(|l| l.split_whitespace().nth(4).unwrap())
+        .collect();
+    assert_eq!(flags, ["-", "-"], "{}", stdout);
+    Ok(())
+}
+
+#[test]
+fn fifo_argument() -> TestResult {
+    let dir = gen_temp_dir();
+    let fifo = dir.join("fifo");
+    std::process::Command::new("mkfifo").arg(&fifo).status()?;
+
+    // FIFO の引数はディレクトリとして開かずに 1 つのエントリとして表示する
+    let output = Command::cargo_bin(PRG)?
+        .args(["-l", "--normalize-special-sizes"])
+        .arg(&fifo)
+        .timeout(std::time::Duration::from_secs(10))
+        .output()?;
+    fs::remove_dir_all(&dir)?;
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stderr)?, "");
+    let stdout = String::from_utf8(output.stdout)?;
+    assert_eq!(stdout.lines().count(), 1, "{}", stdout);
+    let parts: Vec<_> = stdout.split_whitespace().collect();
+    assert_eq!(parts[4], "0");
+    assert_eq!(parts[parts.len() - 1], fifo.display().to_string());
+    Ok(())
+}